@@ -1,71 +1,176 @@
-use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::Path;
 use std::{fs, path::PathBuf};
 
 use anyhow::{Result, anyhow};
-use colored::Colorize;
 use futures::future;
 use log::info;
-use toml::Table;
-use toml::Value as TValue;
 
+use crate::lockfile::Lockfile;
+use crate::manifest::Manifest;
+use crate::registry::{self, CacheMode, Registries, RegistryProvider, SearchEntry};
 use crate::storage;
-use crate::utils::DColor;
+use crate::utils::ver::{Version, VersionReq};
+use crate::workspace::{Selection, Workspace};
 use crate::{
-    dep::{self, DType, Dep},
+    dep::{self, DSource, DType, Dep, UpdatePolicy, api::CratesVersion},
     utils::{self, ColorType},
 };
 
-pub struct Cargo(pub PathBuf);
+pub struct Cargo {
+    pub path: PathBuf,
+    pub registries: Box<dyn RegistryProvider>,
+    /// Package name shown in the headers when working inside a workspace.
+    pub member: Option<String>,
+    /// Limits `update_deps` to these entries of one section, e.g. the
+    /// `[workspace.dependencies]` selected members inherit.
+    pub scope: Option<(DType, HashSet<String>)>,
+}
+
+const WORKSPACE_ROOT: &str = "workspace root";
 
 impl Cargo {
-    pub fn update_dep_type(deps: &Table) -> Result<(Vec<Dep>, Vec<String>)> {
+    pub fn update_dep_type(content: &Manifest, dtype: &DType) -> Result<(Vec<Dep>, Vec<String>)> {
         let mut fds = Vec::new();
         let mut vds = Vec::new();
         info!("init feature and and old version vector");
-        for (k, v) in deps {
-            let d = Dep::from_toml(k, v.clone())?;
+        for d in content.get_deps(dtype)? {
+            if !d.is_updatable() {
+                info!("skip {} ({})", d.name, d.source);
+                continue;
+            }
             vds.push(d.version.clone());
             fds.push(d);
         }
         info!("prepared {} deps to update", fds.len());
         Ok((fds, vds))
     }
-    pub async fn update_deps(&self, ct: ColorType) -> Result<()> {
-        utils::style::print_start_msg("UPDATE DEP(S)");
+    /// Compares requirements by the smallest version they allow, so `1.0` and
+    /// `1.0.0` are equal and `0.10` is newer than `0.9`.
+    fn cmp_reqs(old: &str, new: &str) -> Option<Ordering> {
+        let bound = |r: &str| VersionReq::parse(r).ok().and_then(|r| r.lower_bound());
+        Some(bound(old)?.cmp_precedence(&bound(new)?))
+    }
+    /// Whether moving the requirement leaves the caret range of the old one.
+    fn is_breaking_update(old: &str, new: &str) -> bool {
+        let bound = |r: &str| VersionReq::parse(r).ok().and_then(|r| r.lower_bound());
+        match (bound(old), bound(new)) {
+            (Some(o), Some(n)) => !o.is_caret_compatible(&n),
+            _ => false,
+        }
+    }
+    /// Whether the version the dependency requirement pins was yanked.
+    fn is_yanked(d: &Dep, fd: &dep::api::CratesDep) -> bool {
+        VersionReq::parse(&d.version).is_ok_and(|req| fd.is_yanked(&req))
+    }
+    /// `--rust-version` or else `package.rust-version` of the manifest,
+    /// taken from `[workspace.package]` of the root if inherited.
+    fn msrv<S: AsRef<str>>(&self, content: &Manifest, flag: Option<S>) -> Result<Option<Version>> {
+        let rv = match flag {
+            Some(f) => Some(f.as_ref().to_string()),
+            None if content.inherits_package_field("rust-version") => {
+                let ws = Workspace::find(&self.path)?.ok_or(anyhow!(
+                    "{} inherits rust-version but is not in a workspace",
+                    self.path.display()
+                ))?;
+                let root = match ws.root == self.path {
+                    true => content.clone(),
+                    false => Manifest::load(&ws.root)?,
+                };
+                Some(root.workspace_package_field("rust-version").ok_or(anyhow!(
+                    "no workspace.package.rust-version in {}",
+                    ws.root.display()
+                ))?)
+            }
+            None => content.rust_version(),
+        };
+        match rv {
+            Some(rv) => Ok(Some(Version::parse_rust_version(rv)?)),
+            None => Ok(None),
+        }
+    }
+    /// Explains why `d` did not get the newer version of `free`, which was
+    /// picked ignoring the rust version.
+    fn msrv_note(d: &Dep, free: &Dep, fd: &dep::api::CratesDep) -> Option<String> {
+        if Self::cmp_reqs(&d.version, &free.version) != Some(Ordering::Less) {
+            return None;
+        }
+        let lb = VersionReq::parse(&free.version).ok()?.lower_bound()?;
+        let (vs, _) = fd
+            .versions
+            .iter()
+            .find(|(vs, _)| Version::parse(vs).is_ok_and(|v| v == lb))?;
+        Some(format!("{} needs rust {}", vs, fd.rust_version(vs)?))
+    }
+    fn warn_column(yanked: bool, note: &Option<String>) -> Option<String> {
+        match (yanked, note) {
+            (true, Some(note)) => Some(format!("yanked, {}", note)),
+            (true, None) => Some("yanked".to_string()),
+            (false, note) => note.clone(),
+        }
+    }
+    pub async fn update_deps<S: AsRef<str>>(
+        &self,
+        policy: UpdatePolicy,
+        pre: bool,
+        rust_version: Option<S>,
+        ct: ColorType,
+    ) -> Result<()> {
+        self.print_start_msg("UPDATE DEP(S)");
 
-        info!("parsing Cargo.toml file...");
-        let content = fs::read_to_string(&self.0)?;
-        let mut content = content.parse::<Table>()?;
-        info!("parsed successfully");
+        let mut content = Manifest::load(&self.path)?;
+        let msrv = self.msrv(&content, rust_version)?;
+        let msrv = msrv.as_ref();
+
+        // one registry per `registry = "..."` key in use, `None` is crates.io
+        let mut regs = HashMap::new();
+        for dtype in content.all_dtypes() {
+            for d in content.get_deps(&dtype)? {
+                let key = d.registry().map(String::from);
+                if d.is_updatable() && !regs.contains_key(&key) {
+                    regs.insert(key, self.registries.get(d.registry())?);
+                }
+            }
+        }
+        let regs = &regs;
 
         let mut futures = Vec::new();
 
-        for dtype in [DType::Normal, DType::Dev, DType::Build] {
-            let dtcf = dtype.to_cargo_field();
-            if let Some(TValue::Table(deps)) = content.get(dtcf.as_str()) {
-                info!("fetching {} field", &dtcf);
-                futures.push(async move {
-                    let (fds, vds) = Self::update_dep_type(&deps)?;
-                    let ufds = fds
-                        .into_iter()
-                        .map(|d| d.update_version())
-                        .collect::<Vec<_>>();
-                    let uds = (future::join_all(ufds).await)
-                        .into_iter()
-                        .flatten()
-                        .collect::<Vec<_>>();
-
-                    Ok::<_, anyhow::Error>((dtype, uds, vds))
-                });
-            }
+        for dtype in content.all_dtypes() {
+            info!("fetching {} field", dtype.to_cargo_field());
+            let (fds, vds) = Self::update_dep_type(&content, &dtype)?;
+            let (fds, vds): (Vec<_>, Vec<_>) = fds
+                .into_iter()
+                .zip(vds)
+                .filter(|(d, _)| self.in_scope(&dtype, d))
+                .unzip();
+            futures.push(async move {
+                let ufds = fds
+                    .into_iter()
+                    .map(|d| async move {
+                        let reg = regs[&d.registry().map(String::from)].as_ref();
+                        let fd = reg.fetch(d.crate_name()).await?;
+                        let yanked = Self::is_yanked(&d, &fd);
+                        let ud = d.clone().update_version(&fd, policy, pre, msrv)?;
+                        let note = match msrv {
+                            Some(_) => {
+                                let free = d.update_version(&fd, policy, pre, None)?;
+                                Self::msrv_note(&ud, &free, &fd)
+                            }
+                            None => None,
+                        };
+                        Ok::<_, anyhow::Error>((ud, (yanked, note)))
+                    })
+                    .collect::<Vec<_>>();
+                let (uds, wds): (Vec<_>, Vec<_>) =
+                    future::try_join_all(ufds).await?.into_iter().unzip();
+
+                Ok::<_, anyhow::Error>((dtype, uds, vds, wds))
+            });
         }
         info!("started {} update futures", futures.len());
-        let frs = (future::join_all(futures).await)
-            .into_iter()
-            .flatten()
-            .collect::<Vec<_>>();
+        let frs = future::try_join_all(futures).await?;
         info!("awaited {} futures", frs.len());
 
         let mut mnl = 0;
@@ -73,7 +178,7 @@ impl Cargo {
 
         info!("perform max name and version");
         for fr in &frs {
-            let (_, uds, _) = fr;
+            let (_, uds, vds, _) = fr;
 
             for ud in uds {
                 if mnl < ud.name.len() {
@@ -83,62 +188,134 @@ impl Cargo {
                     mvl = ud.version.len();
                 }
             }
+            for vd in vds {
+                if mvl < vd.len() {
+                    mvl = vd.len();
+                }
+            }
         }
         info!("got {}/{}", mnl, mvl);
 
         let mut real_updated = 0;
         for fr in frs {
-            let (dtype, uds, vds) = fr;
+            let (dtype, uds, vds, wds) = fr;
+            let warns = wds
+                .iter()
+                .map(|(yanked, note)| Self::warn_column(*yanked, note))
+                .collect::<Vec<_>>();
 
             let dtcf = dtype.to_cargo_field();
 
-            if let Some(TValue::Table(deps)) = content.get_mut(&dtcf) {
-                info!("updating {} field", &dtcf);
-                let mut ndeps = Table::new();
-                for ud in &uds {
-                    let (name, attrs) = ud.to_toml();
-                    ndeps.insert(name, attrs);
-                }
-                *deps = ndeps;
-            }
-
-            let mut changed = 0;
+            let mut upgrades = Vec::new();
+            let mut breaking = Vec::new();
+            let mut downgrades = Vec::new();
+            let mut yanked = Vec::new();
+            let mut held = Vec::new();
             for i in 0..uds.len() {
-                if uds[i].version > vds[i] {
-                    changed += 1;
+                // a requirement without a lower bound, like `*`, is only
+                // replaced under `--latest`
+                match Self::cmp_reqs(&vds[i], &uds[i].version) {
+                    Some(Ordering::Less) | None if vds[i] != uds[i].version => {
+                        content.set_dep_version(&dtype, &uds[i].name, &uds[i].version)?;
+                        if Self::is_breaking_update(&vds[i], &uds[i].version) {
+                            breaking.push(i);
+                        } else {
+                            upgrades.push(i);
+                        }
+                    }
+                    Some(Ordering::Greater) => downgrades.push(i),
+                    _ if wds[i].0 => yanked.push(i),
+                    _ if wds[i].1.is_some() => held.push(i),
+                    _ => {}
                 }
             }
+            let changed = upgrades.len() + breaking.len();
             info!("updated {} deps in {}", changed, &dtcf);
+            info!("found {} downgrades in {}", downgrades.len(), &dtcf);
+            info!("found {} yanked deps in {}", yanked.len(), &dtcf);
 
             if changed > 0 {
                 real_updated += 1;
-                utils::style::print_cargo_field(&dtype);
-                if uds.len() != vds.len() {
-                    return Err(anyhow!(
-                        "damn error in fetching and updating deps: {}",
-                        vds.len() - uds.len()
-                    ));
+            }
+            if changed == 0 && downgrades.is_empty() && yanked.is_empty() && held.is_empty() {
+                continue;
+            }
+
+            utils::style::print_cargo_field(&dtype);
+            for i in upgrades {
+                utils::style::print_colored_ref_dep_version_update(
+                    &uds[i],
+                    &vds[i],
+                    mnl,
+                    mvl,
+                    2,
+                    ct.get_dcolor(),
+                    warns[i].as_deref(),
+                );
+            }
+            if !breaking.is_empty() {
+                utils::style::print_breaking_label(2);
+                for i in breaking {
+                    utils::style::print_colored_ref_dep_version_update(
+                        &uds[i],
+                        &vds[i],
+                        mnl,
+                        mvl,
+                        4,
+                        ct.get_dcolor(),
+                        warns[i].as_deref(),
+                    );
                 }
-                for i in 0..uds.len() {
-                    if uds[i].version > vds[i] {
-                        utils::style::print_colored_ref_dep_version_update(
-                            &uds[i],
-                            &vds[i],
-                            mnl,
-                            mvl,
-                            2,
-                            ct.get_dcolor(),
-                        );
-                    }
+            }
+            if !yanked.is_empty() {
+                utils::style::print_yanked_label(2);
+                for i in yanked {
+                    utils::style::print_colored_ref_dep_full(
+                        &uds[i],
+                        mnl,
+                        mvl,
+                        4,
+                        ct.get_dcolor(),
+                        warns[i].as_deref(),
+                    );
+                }
+            }
+            if let Some(msrv) = msrv
+                && !held.is_empty()
+            {
+                utils::style::print_held_back_label(2, msrv.to_string());
+                for i in held {
+                    utils::style::print_colored_ref_dep_full(
+                        &uds[i],
+                        mnl,
+                        mvl,
+                        4,
+                        ct.get_dcolor(),
+                        warns[i].as_deref(),
+                    );
+                }
+            }
+            if !downgrades.is_empty() {
+                utils::style::print_downgrade_label(2);
+                for i in downgrades {
+                    utils::style::print_colored_ref_dep_version_update(
+                        &uds[i],
+                        &vds[i],
+                        mnl,
+                        mvl,
+                        4,
+                        ct.get_dcolor(),
+                        warns[i].as_deref(),
+                    );
                 }
             }
         }
 
         info!("real update {} fields", real_updated);
-        info!("skip saving");
         if real_updated > 0 {
-            info!("saving changes...");
-            fs::write(&self.0, toml::to_string(&content)?)?;
+            content.save(&self.path)?;
+        } else {
+            info!("skip saving");
         }
 
         utils::style::print_end_msg();
@@ -147,43 +324,34 @@ impl Cargo {
     pub async fn init_project<S: AsRef<str>>(
         name: Option<S>,
         deps: Option<S>,
+        regs: &dyn RegistryProvider,
         ct: ColorType,
     ) -> Result<String> {
         utils::style::print_start_msg("INIT PROJECT");
 
-        let mut newc = Table::new();
+        let mut newc = Manifest::default();
 
-        let mut project = Table::new();
         let project_name = if let Some(name) = name {
-            let name = name.as_ref().to_string();
-            name
+            name.as_ref().to_string()
         } else {
             utils::funcs::current_absolute()?
         };
 
-        project.insert("name".to_string(), TValue::String(project_name));
-        project.insert("version".to_string(), TValue::String("0.1.0".to_string()));
-        project.insert("edition".to_string(), TValue::String("2024".to_string()));
-
-        newc.insert("package".to_string(), TValue::Table(project));
+        newc.set_package_field("name", &project_name);
+        newc.set_package_field("version", "0.1.0");
+        newc.set_package_field("edition", "2024");
 
         if let Some(deps) = deps {
             let a_s = storage::AliasStorage::load()?;
             let pdeps = dep::parse::parse_deps(deps.as_ref(), a_s.list())?;
+            let reg = regs.get(None)?;
             let mut fdeps = Vec::new();
             for pd in &pdeps {
-                fdeps.push(dep::api::fetch_crates_dep(&pd.name));
+                fdeps.push(reg.fetch(&pd.name));
             }
 
             let fdl = fdeps.len();
-            let fdeps = (future::join_all(fdeps).await)
-                .into_iter()
-                .flatten()
-                .collect::<Vec<_>>();
-
-            if fdl != fdeps.len() {
-                return Err(anyhow!("error with fetching some deps"));
-            }
+            let fdeps = future::try_join_all(fdeps).await?;
 
             let mut hmdeps = HashMap::new();
 
@@ -191,7 +359,7 @@ impl Cargo {
             let mut mvl = 0;
 
             for i in 0..fdl {
-                let d = dep::normalize(&pdeps[i], &fdeps[i])?;
+                let d = dep::normalize(&pdeps[i], &fdeps[i], false, None)?;
                 if mnl < d.name.len() {
                     mnl = d.name.len();
                 }
@@ -207,50 +375,519 @@ impl Cargo {
             for (t, ds) in hmdeps {
                 utils::style::print_cargo_field(&t);
 
-                let mut tdeps = Table::new();
                 for d in ds {
-                    utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor());
-                    let (name, attrs) = d.to_toml();
-                    tdeps.insert(name, attrs);
+                    utils::style::print_colored_ref_dep_full(
+                        &d,
+                        mnl,
+                        mvl,
+                        2,
+                        ct.get_dcolor(),
+                        None,
+                    );
+                    newc.insert_dep(&t, &d)?;
                 }
+            }
+        }
 
-                newc.insert(t.to_cargo_field(), TValue::Table(tdeps));
+        utils::style::print_end_msg();
+        Ok(newc.to_string())
+    }
+    /// Prints one page of search results, numbered so one of them can be
+    /// picked.
+    pub async fn search<S: AsRef<str>>(
+        query: S,
+        page: usize,
+        registry: Option<S>,
+        regs: &dyn RegistryProvider,
+        ct: ColorType,
+    ) -> Result<Vec<SearchEntry>> {
+        utils::style::print_start_msg("SEARCH");
+
+        let reg = regs.get(registry.as_ref().map(|r| r.as_ref()))?;
+        let page = page.max(1);
+        let res = reg.search(query.as_ref(), page).await?;
+
+        let mnl = res.crates.iter().map(|e| e.name.len()).max().unwrap_or(0);
+        let mvl = res
+            .crates
+            .iter()
+            .map(|e| e.max_version.len())
+            .max()
+            .unwrap_or(0);
+        for (i, e) in res.crates.iter().enumerate() {
+            utils::style::print_colored_search_entry(
+                i + 1,
+                &e.name,
+                &e.max_version,
+                e.downloads,
+                e.description.as_deref(),
+                mnl,
+                mvl,
+                2,
+                ct.get_dcolor(),
+            );
+        }
+        let pages = res.total.div_ceil(registry::SEARCH_PER_PAGE);
+        utils::style::print_search_footer(page, pages, res.total);
+
+        utils::style::print_end_msg();
+        Ok(res.crates)
+    }
+
+    /// Prints what is known about a crate: metadata, recent versions and
+    /// the features of the newest stable (or the `name@req` matching)
+    /// version.
+    pub async fn info<S: AsRef<str>>(
+        dep: S,
+        registry: Option<S>,
+        regs: &dyn RegistryProvider,
+        ct: ColorType,
+    ) -> Result<()> {
+        const RECENT_VERSIONS: usize = 10;
+
+        utils::style::print_start_msg("CRATE INFO");
+
+        let (name, req) = match dep.as_ref().split_once('@') {
+            Some((name, req)) => (name, Some(req)),
+            None => (dep.as_ref(), None),
+        };
+        dep::parse::check_crate_name(name)?;
+        let reg = regs.get(registry.as_ref().map(|r| r.as_ref()))?;
+        let (fdep, meta, owners) =
+            future::join3(reg.fetch(name), reg.metadata(name), reg.owners(name)).await;
+        let fdep = fdep?;
+        let meta = meta.unwrap_or_else(|e| {
+            info!("no metadata for {}: {}", name, e);
+            Default::default()
+        });
+        let owners = owners.unwrap_or_else(|e| {
+            info!("no owners for {}: {}", name, e);
+            Vec::new()
+        });
+
+        let vs = match req {
+            Some(req) if fdep.versions.contains_key(req) => req.to_string(),
+            Some(req) => {
+                let req = VersionReq::parse(req)
+                    .map_err(|e| anyhow!("invalid version {}: {}", req, e))?;
+                fdep.get_last_matching_version(&req, None)?
+            }
+            None => fdep
+                .get_last_version(false, None)
+                .or_else(|_| fdep.get_last_version(true, None))?,
+        };
+        let cv = &fdep.versions[&vs];
+
+        utils::style::print_colored_val_dep_full(
+            fdep.name.as_str(),
+            &vs,
+            None,
+            fdep.name.len(),
+            vs.len(),
+            0,
+            ct.get_dcolor(),
+            cv.yanked.then_some("yanked"),
+        );
+        if let Some(desc) = &meta.description {
+            utils::style::print_description(desc);
+        }
+        let fields = [
+            ("license", meta.license),
+            ("repository", meta.repository),
+            ("homepage", meta.homepage),
+            ("docs", meta.documentation),
+            ("rust-version", cv.rust_version.clone()),
+        ];
+        for (key, val) in fields {
+            if let Some(val) = val {
+                utils::style::print_info_field(key, &val);
+            }
+        }
+        if !owners.is_empty() {
+            let owners = owners
+                .iter()
+                .map(|o| match &o.name {
+                    Some(n) => format!("{} ({})", o.login, n),
+                    None => o.login.clone(),
+                })
+                .collect::<Vec<_>>();
+            utils::style::print_info_field("owners", &owners.join(", "));
+        }
+        let sorted = fdep.sorted_versions();
+        let mut recent = sorted
+            .iter()
+            .take(RECENT_VERSIONS)
+            .map(|(vs, cv)| match cv.yanked {
+                true => format!("{} (yanked)", vs),
+                false => vs.to_string(),
+            })
+            .collect::<Vec<_>>();
+        if sorted.len() > RECENT_VERSIONS {
+            recent.push(format!("... {} total", sorted.len()));
+        }
+        utils::style::print_info_field("versions", &recent.join(", "));
+
+        let defaults = cv.features.get("default").cloned().unwrap_or_default();
+        let on_by_default = cv.feature_closure(&["default"]);
+        let default = match defaults.is_empty() {
+            true => "-".to_string(),
+            false => defaults.join(", "),
+        };
+        utils::style::print_info_field("default", &default);
+        let features = cv
+            .features
+            .iter()
+            .filter(|(f, _)| *f != "default")
+            .collect::<Vec<_>>();
+        if !features.is_empty() {
+            utils::style::print_info_section("features");
+            let mnl = features.iter().map(|(f, _)| f.len()).max().unwrap_or(0);
+            for (f, en) in features {
+                utils::style::print_colored_feature(
+                    f,
+                    en,
+                    &Self::implied_features(cv, f, en),
+                    if on_by_default.contains(f) { '*' } else { ' ' },
+                    mnl,
+                    2,
+                    ct.get_dcolor(),
+                );
             }
         }
 
         utils::style::print_end_msg();
-        Ok(toml::to_string(&newc)?)
+        Ok(())
     }
-    pub async fn append_deps<S: AsRef<str>>(&self, deps: S, ct: ColorType) -> Result<()> {
-        utils::style::print_start_msg("ADD DEP(S)");
 
-        let content = fs::read_to_string(&self.0)?;
-        let mut content = content.parse::<Table>()?;
+    /// Every section declaring the dependency, with its entry there.
+    fn find_dep(content: &Manifest, name: &str) -> Result<Vec<(DType, Dep)>> {
+        let mut res = Vec::new();
+        for dtype in content.dtypes() {
+            if let Some(d) = content.get_dep(&dtype, name)? {
+                res.push((dtype, d));
+            }
+        }
+        if res.is_empty() {
+            return Err(anyhow!("{} is not a dependency", name));
+        }
+        Ok(res)
+    }
+    /// Entries of the workspace root's `[workspace.dependencies]`, what
+    /// `workspace = true` resolves to. Empty outside a workspace.
+    fn inheritable(&self) -> Result<HashMap<String, Dep>> {
+        let Some(ws) = Workspace::find(&self.path)? else {
+            return Ok(HashMap::new());
+        };
+        Ok(Manifest::load(&ws.root)?
+            .get_deps(&DType::Workspace)?
+            .into_iter()
+            .map(|d| (d.name.clone(), d))
+            .collect())
+    }
+    /// The entry with `workspace = true` resolved against the root's one:
+    /// its requirement and source, the features of both.
+    fn inherited(d: &Dep, inheritable: &HashMap<String, Dep>) -> Dep {
+        match (&d.source, inheritable.get(&d.name)) {
+            (DSource::Workspace, Some(root)) => {
+                let mut features = root.features.clone().unwrap_or_default();
+                for f in d.features.iter().flatten() {
+                    if !features.contains(f) {
+                        features.push(f.to_string());
+                    }
+                }
+                Dep {
+                    features: (!features.is_empty()).then_some(features),
+                    optional: d.optional,
+                    ..root.clone()
+                }
+            }
+            _ => d.clone(),
+        }
+    }
+    /// Version the requirement resolves to with its data: the locked one,
+    /// else the newest it matches. `None` for dependencies which are not
+    /// from a registry.
+    async fn dep_features(
+        &self,
+        d: &Dep,
+        lock: Option<&Lockfile>,
+        package: Option<&str>,
+    ) -> Result<Option<(String, CratesVersion)>> {
+        if !d.is_updatable() {
+            return Ok(None);
+        }
+        let reg = self.registries.get(d.registry())?;
+        let fd = reg.fetch(d.crate_name()).await?;
+        let req = VersionReq::parse(&d.version)
+            .map_err(|e| anyhow!("invalid version {} of {}: {}", d.version, d.name, e))?;
+        let locked = lock
+            .and_then(|l| l.locked_version(package, d.crate_name(), &d.version))
+            .filter(|v| fd.versions.contains_key(*v));
+        let vs = match locked {
+            Some(v) => v.to_string(),
+            None => fd.get_last_matching_version(&req, None)?,
+        };
+        let cv = fd.versions.get(&vs).cloned().unwrap_or_default();
+        Ok(Some((vs, cv)))
+    }
+    /// What the feature turns on only through the features it enables.
+    fn implied_features(cv: &CratesVersion, f: &str, enables: &[String]) -> Vec<String> {
+        cv.feature_closure(&[f])
+            .into_iter()
+            .filter(|e| e != f && !enables.contains(e))
+            .collect()
+    }
+    /// Enabled features another enabled one (or `default`) already turns
+    /// on, and what `default-features = false` drops.
+    fn feature_warnings(d: &Dep, cv: &CratesVersion) -> Vec<String> {
+        let enabled = d.features.clone().unwrap_or_default();
+        let mut roots = enabled.clone();
+        if d.default_features != Some(false) && cv.features.contains_key("default") {
+            roots.push("default".to_string());
+        }
+
+        let mut res = Vec::new();
+        for (f, by) in cv.redundant_features(&roots) {
+            if enabled.contains(&f) {
+                res.push(format!("{} is already enabled by {}", f, by));
+            }
+        }
+        if d.default_features == Some(false) {
+            let dropped = cv.dropped_defaults(&enabled);
+            if !dropped.is_empty() {
+                res.push(format!(
+                    "default-features = false drops {}",
+                    dropped.join(", ")
+                ));
+            }
+        }
+        res
+    }
+    fn parse_features<S: AsRef<str>>(features: S) -> Result<Vec<String>> {
+        let res = features
+            .as_ref()
+            .split(',')
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect::<Vec<_>>();
+        if res.is_empty() {
+            return Err(anyhow!("no features given"));
+        }
+        Ok(res)
+    }
+    fn max_lens(found: &[(DType, Dep)]) -> (usize, usize) {
+        let mnl = found.iter().map(|(_, d)| d.name.len()).max().unwrap_or(0);
+        let mvl = found
+            .iter()
+            .map(|(_, d)| d.version_label().len())
+            .max()
+            .unwrap_or(0);
+        (mnl, mvl)
+    }
+
+    pub async fn add_features<S: AsRef<str>>(
+        &self,
+        name: S,
+        features: S,
+        ct: ColorType,
+    ) -> Result<()> {
+        self.print_start_msg("ADD FEATURE(S)");
+
+        let mut content = Manifest::load(&self.path)?;
+        let features = Self::parse_features(features)?;
+        let found = Self::find_dep(&content, name.as_ref())?;
+        let inheritable = self.inheritable()?;
+        let lock = Self::find_lock(&self.path);
+        let package = content.package_field("name");
+
+        let mut cvs = Vec::new();
+        for (_, d) in &found {
+            let d = Self::inherited(d, &inheritable);
+            let fd = self
+                .dep_features(&d, lock.as_ref(), package.as_deref())
+                .await?;
+            if let Some((vs, cv)) = &fd
+                && let Some(f) = features.iter().find(|f| !cv.features.contains_key(*f))
+            {
+                return Err(anyhow!("{} {} has no feature {}", d.name, vs, f));
+            }
+            cvs.push(fd.map(|(_, cv)| cv));
+        }
+
+        let (mnl, mvl) = Self::max_lens(&found);
+        for ((dtype, d), cv) in found.into_iter().zip(cvs) {
+            content.add_dep_features(&dtype, &d.name, &features)?;
+            let d = content.get_dep(&dtype, &d.name)?.unwrap_or(d);
+            utils::style::print_cargo_field(&dtype);
+            utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor(), None);
+            let d = Self::inherited(&d, &inheritable);
+            for w in cv
+                .map(|cv| Self::feature_warnings(&d, &cv))
+                .unwrap_or_default()
+            {
+                utils::style::print_warning(4, w);
+            }
+        }
+
+        utils::style::print_end_msg();
+        content.save(&self.path)?;
+        Ok(())
+    }
+    pub async fn remove_features<S: AsRef<str>>(
+        &self,
+        name: S,
+        features: S,
+        ct: ColorType,
+    ) -> Result<()> {
+        self.print_start_msg("REMOVE FEATURE(S)");
+
+        let mut content = Manifest::load(&self.path)?;
+        let features = Self::parse_features(features)?;
+        let found = Self::find_dep(&content, name.as_ref())?;
+
+        let enabled = |f: &String| {
+            found
+                .iter()
+                .any(|(_, d)| d.features.as_ref().is_some_and(|fs| fs.contains(f)))
+        };
+        if let Some(f) = features.iter().find(|f| !enabled(f)) {
+            return Err(anyhow!("{} is not enabled for {}", f, name.as_ref()));
+        }
+
+        let (mnl, mvl) = Self::max_lens(&found);
+        for (dtype, d) in found {
+            content.remove_dep_features(&dtype, &d.name, &features)?;
+            let d = content.get_dep(&dtype, &d.name)?.unwrap_or(d);
+            utils::style::print_cargo_field_a(&dtype);
+            utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor(), None);
+        }
+
+        utils::style::print_end_msg();
+        content.save(&self.path)?;
+        Ok(())
+    }
+    /// Features of the dependency in every section, `+` marks the enabled
+    /// ones and `*` the ones turned on through them or `default`.
+    pub async fn list_features<S: AsRef<str>>(&self, name: S, ct: ColorType) -> Result<()> {
+        self.print_start_msg("LIST FEATURE(S)");
+
+        let content = Manifest::load(&self.path)?;
+        let found = Self::find_dep(&content, name.as_ref())?;
+        let inheritable = self.inheritable()?;
+        let lock = Self::find_lock(&self.path);
+        let package = content.package_field("name");
+
+        let (mnl, mvl) = Self::max_lens(&found);
+        for (dtype, d) in found {
+            utils::style::print_cargo_field(&dtype);
+            utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor(), None);
+
+            let d = Self::inherited(&d, &inheritable);
+            let fd = self
+                .dep_features(&d, lock.as_ref(), package.as_deref())
+                .await?;
+            let Some((vs, cv)) = fd else {
+                utils::style::print_description(format!(
+                    "    features of {} are not known",
+                    d.source
+                ));
+                continue;
+            };
+            if cv.features.is_empty() {
+                utils::style::print_description(format!("    {} {} has no features", d.name, vs));
+                continue;
+            }
+            let enabled = d.features.clone().unwrap_or_default();
+            let mut roots = enabled.clone();
+            if d.default_features != Some(false) {
+                roots.push("default".to_string());
+            }
+            let on = cv.feature_closure(&roots);
+            let fnl = cv.features.keys().map(|f| f.len()).max().unwrap_or(0);
+            for (f, en) in cv.features.iter().filter(|(f, _)| *f != "default") {
+                let mark = if enabled.contains(f) {
+                    '+'
+                } else if on.contains(f) {
+                    '*'
+                } else {
+                    ' '
+                };
+                utils::style::print_colored_feature(
+                    f,
+                    en,
+                    &Self::implied_features(&cv, f, en),
+                    mark,
+                    fnl,
+                    4,
+                    ct.get_dcolor(),
+                );
+            }
+            for w in Self::feature_warnings(&d, &cv) {
+                utils::style::print_warning(4, w);
+            }
+        }
+
+        utils::style::print_end_msg();
+        Ok(())
+    }
+
+    pub async fn append_deps<S: AsRef<str>>(
+        &self,
+        deps: S,
+        registry: Option<S>,
+        pre: bool,
+        rust_version: Option<S>,
+        workspace_dep: bool,
+        ct: ColorType,
+    ) -> Result<()> {
+        self.print_start_msg("ADD DEP(S)");
+
+        self.check_not_virtual()?;
+        let mut content = Manifest::load(&self.path)?;
+        // with `workspace_dep` the version goes to the root and the member
+        // inherits it
+        let ws_root = match workspace_dep {
+            true => Some(
+                Workspace::find(&self.path)?
+                    .ok_or(anyhow!("{} is not in a workspace", self.path.display()))?
+                    .root,
+            ),
+            false => None,
+        };
+        let mut root = match &ws_root {
+            Some(r) if *r != self.path => Some(Manifest::load(r)?),
+            _ => None,
+        };
+        let msrv = self.msrv(&content, rust_version)?;
+        let msrv = msrv.as_ref();
 
         let a_s = storage::AliasStorage::load()?;
         let pdeps = dep::parse::parse_deps(deps.as_ref(), a_s.list())?;
+        let registry = registry.as_ref().map(|r| r.as_ref());
+        let reg = self.registries.get(registry)?;
         let mut fdeps = Vec::new();
         for pd in &pdeps {
-            fdeps.push(dep::api::fetch_crates_dep(&pd.name));
+            fdeps.push(reg.fetch(&pd.name));
         }
         let fdl = fdeps.len();
-        let fdeps = (future::join_all(fdeps).await)
-            .into_iter()
-            .flatten()
-            .collect::<Vec<_>>();
-        if fdl != fdeps.len() {
-            return Err(anyhow!(
-                "error fetching some dependencies: {}",
-                fdl - fdeps.len()
-            ));
-        }
+        let fdeps = future::try_join_all(fdeps).await?;
 
         let mut mnl = 0;
         let mut mvl = 0;
 
         let mut hmdeps = HashMap::new();
+        let mut notes = HashMap::new();
         for i in 0..fdl {
-            let d = dep::normalize(&pdeps[i], &fdeps[i])?;
+            let mut d = dep::normalize(&pdeps[i], &fdeps[i], pre, msrv)?;
+            if let Some(r) = registry {
+                d.source = DSource::Registry(r.to_string());
+            }
+            if msrv.is_some()
+                && let Ok(free) = dep::normalize(&pdeps[i], &fdeps[i], pre, None)
+                && let Some(note) = Self::msrv_note(&d, &free, &fdeps[i])
+            {
+                notes.insert(d.name.clone(), note);
+            }
             if mnl < d.name.len() {
                 mnl = d.name.len();
             }
@@ -264,155 +901,1551 @@ impl Cargo {
                 .or_insert(vec![d]);
         }
 
+        let mut inherited = Vec::new();
         for (t, ds) in hmdeps {
             utils::style::print_cargo_field(&t);
 
-            match content.get_mut(&t.to_cargo_field()) {
-                Some(TValue::Table(deps)) => {
-                    for d in ds {
-                        utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor());
-                        let (name, attrs) = d.to_toml();
-                        deps.insert(name, attrs);
+            for d in ds {
+                let warn = notes.get(&d.name).map(|n: &String| n.as_str());
+                let d = match ws_root {
+                    Some(_) => {
+                        inherited.push(d.clone());
+                        Self::inheriting(&d)
                     }
+                    None => d,
+                };
+                utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor(), warn);
+                content.insert_dep(&t, &d)?;
+            }
+        }
+        if !inherited.is_empty() {
+            utils::style::print_cargo_field(&DType::Workspace);
+            let rc = root.as_mut().unwrap_or(&mut content);
+            for d in inherited {
+                if let Some(old) = rc.get_dep(&DType::Workspace, &d.name)? {
+                    utils::style::print_colored_ref_dep_full(
+                        &old,
+                        mnl,
+                        mvl,
+                        2,
+                        ct.get_dcolor(),
+                        Some("kept"),
+                    );
+                    continue;
+                }
+                let d = Dep {
+                    features: None,
+                    optional: None,
+                    ..d
+                };
+                utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor(), None);
+                rc.insert_dep(&DType::Workspace, &d)?;
+            }
+        }
+
+        content.save(&self.path)?;
+        if let (Some(root), Some(path)) = (root, ws_root) {
+            root.save(path)?;
+        }
+
+        utils::style::print_end_msg();
+        Ok(())
+    }
+    /// Moves registry dependencies several members declare into
+    /// `[workspace.dependencies]`, at the highest requirement among them (or
+    /// the one already there). Members then inherit them, keeping their
+    /// features and `optional`, the other keys move to the root entry and
+    /// must be the same in every member. Named dependencies are hoisted even
+    /// if only one member has them.
+    pub fn hoist<S: AsRef<str>>(&self, names: Option<S>, ct: ColorType) -> Result<()> {
+        utils::style::print_start_msg("HOIST DEP(S)");
+
+        let ws = Workspace::find(&self.path)?
+            .ok_or(anyhow!("{} is not in a workspace", self.path.display()))?;
+        let only = names.map(|n| {
+            n.as_ref()
+                .split(',')
+                .map(|n| n.trim().to_string())
+                .filter(|n| !n.is_empty())
+                .collect::<HashSet<_>>()
+        });
+
+        let mut manifests = BTreeMap::new();
+        manifests.insert(ws.root.clone(), Manifest::load(&ws.root)?);
+        for m in &ws.members {
+            if !manifests.contains_key(&m.path) {
+                manifests.insert(m.path.clone(), Manifest::load(&m.path)?);
+            }
+        }
+
+        // entries by name: member, its manifest, section and the entry
+        let mut found = BTreeMap::new();
+        for m in &ws.members {
+            let content = &manifests[&m.path];
+            for dtype in content.dtypes() {
+                for d in content.get_deps(&dtype)? {
+                    if d.is_updatable() && only.as_ref().is_none_or(|o| o.contains(&d.name)) {
+                        found.entry(d.name.clone()).or_insert(Vec::new()).push((
+                            m.name.clone(),
+                            m.path.clone(),
+                            dtype.clone(),
+                            d,
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut hoisted = Vec::new();
+        let mut skipped = Vec::new();
+        let mut changed = HashSet::new();
+        for (name, uses) in found {
+            let members = uses
+                .iter()
+                .map(|(m, _, _, _)| m.to_string())
+                .collect::<BTreeSet<_>>();
+            let existing = manifests[&ws.root].get_dep(&DType::Workspace, &name)?;
+            if existing.is_none() && members.len() < 2 && only.is_none() {
+                continue;
+            }
+
+            let first = &uses[0].3;
+            if uses
+                .iter()
+                .any(|(_, _, _, d)| d.source != first.source || d.package != first.package)
+            {
+                skipped.push(format!("{} comes from different sources", name));
+                continue;
+            }
+            let no_default = uses
+                .iter()
+                .all(|(_, _, _, d)| d.default_features == Some(false));
+            if !no_default
+                && uses
+                    .iter()
+                    .any(|(_, _, _, d)| d.default_features == Some(false))
+            {
+                skipped.push(format!(
+                    "{} has default-features = false in some members only",
+                    name
+                ));
+                continue;
+            }
+            // the rest of the keys can only be set in the root entry
+            let extra = |d: &Dep| {
+                d.extra
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string().trim().to_string()))
+                    .collect::<BTreeSet<_>>()
+            };
+            if uses.iter().any(|(_, _, _, d)| extra(d) != extra(first)) {
+                skipped.push(format!(
+                    "{} has keys other than features and optional that differ between members",
+                    name
+                ));
+                continue;
+            }
+            let newest = uses
+                .iter()
+                .map(|(_, _, _, d)| d.version.as_str())
+                .max_by(|a, b| Self::cmp_reqs(a, b).unwrap_or(Ordering::Equal))
+                .unwrap_or_default();
+
+            let root_d = match existing {
+                Some(e) => {
+                    if Self::cmp_reqs(&e.version, newest) == Some(Ordering::Less) {
+                        skipped.push(format!(
+                            "{} {} in workspace.dependencies is older than {}",
+                            name, e.version, newest
+                        ));
+                        continue;
+                    }
+                    if (e.default_features == Some(false)) != no_default
+                        || extra(&e) != extra(first)
+                    {
+                        skipped.push(format!(
+                            "{} in workspace.dependencies has other keys than the members",
+                            name
+                        ));
+                        continue;
+                    }
+                    e
                 }
-                Some(_) => println!("tipatipitiritoutou"),
                 None => {
-                    let mut deps = Table::new();
-                    for d in ds {
-                        utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor());
-                        let (name, attrs) = d.to_toml();
-                        deps.insert(name, attrs);
+                    let root_d = Dep {
+                        name: name.clone(),
+                        version: newest.to_string(),
+                        source: first.source.clone(),
+                        package: first.package.clone(),
+                        default_features: no_default.then_some(false),
+                        extra: first.extra.clone(),
+                        ..Default::default()
+                    };
+                    if let Some(rc) = manifests.get_mut(&ws.root) {
+                        rc.insert_dep(&DType::Workspace, &root_d)?;
                     }
-                    content.insert(t.to_cargo_field(), TValue::Table(deps));
+                    changed.insert(ws.root.clone());
+                    root_d
                 }
+            };
+            for (_, path, dtype, d) in &uses {
+                if let Some(content) = manifests.get_mut(path) {
+                    content.insert_dep(dtype, &Self::inheriting(d))?;
+                }
+                changed.insert(path.clone());
             }
+            hoisted.push((root_d, members));
         }
 
-        fs::write(&self.0, toml::to_string(&content)?)?;
+        if hoisted.is_empty() {
+            utils::style::print_description("nothing to hoist");
+        } else {
+            let mnl = hoisted.iter().map(|(d, _)| d.name.len()).max().unwrap_or(0);
+            let mvl = hoisted
+                .iter()
+                .map(|(d, _)| d.version_label().len())
+                .max()
+                .unwrap_or(0);
+            utils::style::print_cargo_field(&DType::Workspace);
+            for (d, members) in &hoisted {
+                utils::style::print_colored_ref_dep_full(d, mnl, mvl, 2, ct.get_dcolor(), None);
+                utils::style::print_description(format!(
+                    "    inherited by {}",
+                    members.iter().cloned().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        for s in skipped {
+            utils::style::print_warning(2, s);
+        }
 
+        for (path, content) in manifests {
+            if changed.contains(&path) {
+                content.save(&path)?;
+            }
+        }
         utils::style::print_end_msg();
         Ok(())
     }
-    pub async fn remove_deps<S: AsRef<str>>(&self, names: S, ct: ColorType) -> Result<()> {
-        utils::style::print_start_msg("REMOVE DEP(S)");
+    /// Removes the dependencies from every section of the manifest. With
+    /// `workspace_dep` also from `[workspace.dependencies]` of the root.
+    pub async fn remove_deps<S: AsRef<str>>(
+        &self,
+        names: S,
+        workspace_dep: bool,
+        ct: ColorType,
+    ) -> Result<()> {
+        self.print_start_msg("REMOVE DEP(S)");
 
-        let mut content = fs::read_to_string(&self.0)?.parse::<Table>()?;
+        let mut content = Manifest::load(&self.path)?;
         let names = names.as_ref().trim().split(",").collect::<HashSet<_>>();
+        let ws_root = match workspace_dep {
+            true => Some(self.uninherited_root(&names)?),
+            false => None,
+        };
+        let here = fs::canonicalize(&self.path)?;
+        let mut root = match &ws_root {
+            Some(r) if *r != here => Some(Manifest::load(r)?),
+            _ => None,
+        };
 
         let mut mnl = 0;
         let mut mvl = 0;
 
-        for dtype in [DType::Normal, DType::Dev, DType::Build] {
-            let dtcf = dtype.to_cargo_field();
-            if let Some(TValue::Table(deps)) = content.get(&dtcf) {
-                for (k, v) in deps.iter() {
-                    if names.contains(&k.as_str()) {
-                        let d = Dep::from_toml(k, v.clone())?;
-                        if d.name.len() > mnl {
-                            mnl = d.name.len();
-                        }
-                        if d.version.len() > mvl {
-                            mvl = d.version.len();
-                        }
+        // sections with whether they are in the root
+        let mut dtypes = content
+            .dtypes()
+            .into_iter()
+            .map(|t| (t, false))
+            .collect::<Vec<_>>();
+        if ws_root.is_some() {
+            dtypes.push((DType::Workspace, true));
+        }
+        for (dtype, in_root) in &dtypes {
+            let m = match in_root {
+                true => root.as_ref().unwrap_or(&content),
+                false => &content,
+            };
+            for name in &names {
+                if let Some(d) = m.get_dep(dtype, name)? {
+                    if d.name.len() > mnl {
+                        mnl = d.name.len();
+                    }
+                    if d.version_label().len() > mvl {
+                        mvl = d.version_label().len();
                     }
                 }
             }
         }
 
-        for dtype in [DType::Normal, DType::Dev, DType::Build] {
-            let dtcf = dtype.to_cargo_field();
-            if let Some(TValue::Table(deps)) = content.get_mut(&dtcf) {
-                let mut removed_deps = Vec::new();
-                for (k, v) in deps.iter() {
-                    if names.contains(&k.as_str()) {
-                        let d = Dep::from_toml(k, v.clone())?;
-                        removed_deps.push(d);
-                    }
+        for (dtype, in_root) in dtypes {
+            let m = match in_root {
+                true => root.as_mut().unwrap_or(&mut content),
+                false => &mut content,
+            };
+            let mut removed_deps = Vec::new();
+            for name in &names {
+                if let Some(d) = m.remove_dep(&dtype, name)? {
+                    removed_deps.push(d);
                 }
+            }
 
-                if removed_deps.is_empty() {
-                    continue;
+            if removed_deps.is_empty() {
+                continue;
+            }
+
+            utils::style::print_cargo_field_a(&dtype);
+            for d in removed_deps {
+                utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor(), None);
+            }
+        }
+
+        utils::style::print_end_msg();
+        content.save(&self.path)?;
+        if let (Some(root), Some(path)) = (root, ws_root) {
+            root.save(path)?;
+        }
+        Ok(())
+    }
+    /// Root of the workspace, once no member but this one inherits the
+    /// named entries of its `[workspace.dependencies]`.
+    fn uninherited_root(&self, names: &HashSet<&str>) -> Result<PathBuf> {
+        let here = fs::canonicalize(&self.path)?;
+        let ws = Workspace::find(&here)?
+            .ok_or(anyhow!("{} is not in a workspace", self.path.display()))?;
+        for m in ws.members.iter().filter(|m| m.path != here) {
+            let content = Manifest::load(&m.path)?;
+            for dtype in content.dtypes() {
+                for name in names {
+                    if content
+                        .get_dep(&dtype, name)?
+                        .is_some_and(|d| d.source == DSource::Workspace)
+                    {
+                        return Err(anyhow!(
+                            "{} is still inherited by {} in {}",
+                            name,
+                            m.name,
+                            dtype.to_cargo_field()
+                        ));
+                    }
                 }
+            }
+        }
+        Ok(ws.root)
+    }
+    pub async fn list(&self, ct: ColorType) -> Result<()> {
+        self.print_start_msg("LIST DEP(S)");
 
-                utils::style::print_cargo_field_a(&dtype);
-                for d in removed_deps {
-                    utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor());
+        let content = Manifest::load(&self.path)?;
+        let hmdeps = Self::deps_by_type(&content)?;
+        let lock = Self::find_lock(&self.path);
+        let package = content.package_field("name");
+        let known = self.fetch_known(hmdeps.values().flatten()).await;
+
+        let all = hmdeps.values().flatten();
+        let mnl = all.clone().map(|d| d.name.len()).max().unwrap_or(0);
+        let total = all.count();
+
+        // version column with the locked version, and the warnings
+        let mut rows = BTreeMap::new();
+        for (t, ds) in hmdeps {
+            let mut tds = Vec::new();
+            for d in ds {
+                let fd = known.get(&Self::yank_key(&d));
+                let locked = lock
+                    .as_ref()
+                    .and_then(|l| l.locked_version(package.as_deref(), d.crate_name(), &d.version));
+
+                let mut notes = Vec::new();
+                if fd.is_some_and(|fd| Self::is_yanked(&d, fd)) {
+                    notes.push("yanked".to_string());
                 }
-                deps.retain(|k, _| !names.contains(&k));
-                if deps.is_empty() {
-                    content.remove(&dtcf);
+                if let (Some(fd), Some(locked)) = (fd, locked)
+                    && let Some(newer) = Self::lock_behind(&d, fd, locked)
+                {
+                    notes.push(format!("lock behind {}", newer));
                 }
+                let label = match locked {
+                    Some(l) => format!("{} ({})", d.version_label(), l),
+                    None => d.version_label(),
+                };
+                tds.push((d, label, notes.join(", ")));
+            }
+            rows.insert(t, tds);
+        }
+        let mvl = rows
+            .values()
+            .flatten()
+            .map(|(_, l, _)| l.len())
+            .max()
+            .unwrap_or(0);
+
+        for (t, ds) in rows {
+            utils::style::print_total_dependencies(total);
+            utils::style::print_cargo_field(&t);
+            for (d, label, notes) in ds {
+                let warn = (!notes.is_empty()).then_some(notes.as_str());
+                utils::style::print_colored_val_dep_full(
+                    &d.name,
+                    &label,
+                    d.features.as_deref(),
+                    mnl,
+                    mvl,
+                    2,
+                    ct.get_dcolor(),
+                    warn,
+                );
             }
         }
 
         utils::style::print_end_msg();
-        fs::write(&self.0, toml::to_string(&content)?)?;
         Ok(())
     }
-    async fn _get_deps_from_value(t: &Table) -> Vec<Dep> {
-        t.iter()
-            .map(|(dk, dv)| Dep::from_toml(dk, dv.clone()))
-            .flatten()
-            .collect()
+    /// Dependencies of every section of the manifest, empty ones left out.
+    fn deps_by_type(content: &Manifest) -> Result<BTreeMap<DType, Vec<Dep>>> {
+        let mut hmdeps = BTreeMap::new();
+        for dtype in content.all_dtypes() {
+            let ds = content.get_deps(&dtype)?;
+            if !ds.is_empty() {
+                hmdeps.insert(dtype, ds);
+            }
+        }
+        Ok(hmdeps)
     }
-    pub async fn list(&self, ct: ColorType) -> Result<()> {
-        utils::style::print_start_msg("LIST DEP(S)");
+    /// Groups the registry dependencies of all workspace members by crate
+    /// and reports the ones whose requirements or features differ between
+    /// entries. Inherited entries count with the root's requirement. With
+    /// `unify` the lower requirements are raised to the highest one.
+    pub fn consistency(&self, unify: bool, ct: ColorType) -> Result<()> {
+        utils::style::print_start_msg("CONSISTENCY");
 
-        let content = fs::read_to_string(&self.0)?.parse::<Table>()?;
+        let ws = Workspace::find(&self.path)?
+            .ok_or(anyhow!("{} is not in a workspace", self.path.display()))?;
+        let mut manifests = BTreeMap::new();
+        manifests.insert(ws.root.clone(), Manifest::load(&ws.root)?);
+        for m in &ws.members {
+            if !manifests.contains_key(&m.path) {
+                manifests.insert(m.path.clone(), Manifest::load(&m.path)?);
+            }
+        }
+        let inheritable = manifests[&ws.root]
+            .get_deps(&DType::Workspace)?
+            .into_iter()
+            .map(|d| (d.name.clone(), d))
+            .collect::<HashMap<_, _>>();
 
-        let mut mnl = 0;
-        let mut mvl = 0;
+        // entries by registry and crate: member, its manifest, section, the
+        // entry as written and with inheritance resolved
+        let mut groups = BTreeMap::new();
+        for m in &ws.members {
+            for (dtype, ds) in Self::deps_by_type(&manifests[&m.path])? {
+                if dtype == DType::Workspace {
+                    continue;
+                }
+                for d in ds {
+                    let eff = Self::inherited(&d, &inheritable);
+                    if !eff.is_updatable() {
+                        continue;
+                    }
+                    let key = (
+                        eff.registry().map(String::from),
+                        eff.crate_name().to_string(),
+                    );
+                    groups.entry(key).or_insert(Vec::new()).push((
+                        m.name.clone(),
+                        m.path.clone(),
+                        dtype.clone(),
+                        d,
+                        eff,
+                    ));
+                }
+            }
+        }
 
-        let mut hmdeps = HashMap::new();
-        let mut total = 0;
+        let feature_set = |d: &Dep| {
+            let mut fs = d.features.clone().unwrap_or_default();
+            fs.sort();
+            (fs, d.default_features != Some(false))
+        };
+        let label = |m: &str, dtype: &DType| match dtype {
+            DType::Normal => m.to_string(),
+            t => format!("{} ({})", m, t),
+        };
 
-        for dtype in [DType::Normal, DType::Dev, DType::Build] {
-            let dtcf = dtype.to_cargo_field();
-            if let Some(TValue::Table(deps)) = content.get(&dtcf) {
-                for (n, ats) in deps {
-                    let d = Dep::from_toml(n, ats.clone())?;
-                    if mnl < d.name.len() {
-                        mnl = d.name.len();
-                    }
-                    if mvl < d.version.len() {
-                        mvl = d.version.len();
-                    }
+        let shared = groups.values().filter(|us| us.len() > 1).count();
+        let mut divergent = 0;
+        let mut changed = HashSet::new();
+        for ((_, name), uses) in &groups {
+            let versions = uses
+                .iter()
+                .map(|u| u.4.version.as_str())
+                .collect::<BTreeSet<_>>();
+            let features = uses
+                .iter()
+                .map(|u| feature_set(&u.4))
+                .collect::<HashSet<_>>();
+            if versions.len() < 2 && features.len() < 2 {
+                continue;
+            }
+            divergent += 1;
+            let highest = versions
+                .iter()
+                .copied()
+                .max_by(|a, b| Self::cmp_reqs(a, b).unwrap_or(Ordering::Equal))
+                .unwrap_or_default();
+
+            utils::style::print_info_section(name);
+            let mnl = uses
+                .iter()
+                .map(|u| label(&u.0, &u.2).len())
+                .max()
+                .unwrap_or(0);
+            let mvl = uses.iter().map(|u| u.4.version.len()).max().unwrap_or(0);
+            let mut raised = HashSet::new();
+            for (m, path, dtype, d, eff) in uses {
+                let lower = versions.len() > 1 && eff.version != highest;
+                let inherited = d.source == DSource::Workspace;
+                let warn = match (lower, inherited) {
+                    (true, _) if unify => None,
+                    (true, true) => Some("lower, inherited"),
+                    (true, false) => Some("lower"),
+                    (false, true) => Some("inherited"),
+                    (false, false) => None,
+                };
+                let (m, dfeat) = (label(m, dtype), eff.features.as_deref());
+                if !(lower && unify) {
+                    utils::style::print_colored_val_dep_full(
+                        &m,
+                        &eff.version,
+                        dfeat,
+                        mnl,
+                        mvl,
+                        2,
+                        ct.get_dcolor(),
+                        warn,
+                    );
+                    continue;
+                }
 
-                    hmdeps
-                        .entry(dtype.clone())
-                        .and_modify(|tds: &mut Vec<Dep>| tds.push(d.clone()))
-                        .or_insert(vec![d]);
-                    total += 1;
+                // inherited entries are raised once, in the root
+                let (path, dtype, dname) = match inherited {
+                    true => (&ws.root, &DType::Workspace, &d.name),
+                    false => (path, dtype, &d.name),
+                };
+                if raised.insert((path.clone(), dtype.clone(), dname.clone()))
+                    && let Some(content) = manifests.get_mut(path)
+                {
+                    content.set_dep_version(dtype, dname.as_str(), highest)?;
+                    changed.insert(path.clone());
                 }
+                utils::style::print_colored_val_dep_version_update(
+                    m.as_str(),
+                    highest,
+                    &eff.version,
+                    mnl,
+                    mvl,
+                    2,
+                    ct.get_dcolor(),
+                    inherited.then_some("inherited"),
+                );
+            }
+            if features.len() > 1 {
+                utils::style::print_warning(2, "features differ");
             }
         }
 
-        for (t, ds) in hmdeps {
-            utils::style::print_total_dependencies(total);
+        utils::style::print_description(format!(
+            "{} crates shared by several entries, {} divergent",
+            shared, divergent
+        ));
+        for (path, content) in manifests {
+            if changed.contains(&path) {
+                content.save(&path)?;
+            }
+        }
+        utils::style::print_end_msg();
+        Ok(())
+    }
+    fn yank_key(d: &Dep) -> (Option<String>, String, String) {
+        (
+            d.registry().map(String::from),
+            d.crate_name().to_string(),
+            d.version.to_string(),
+        )
+    }
+    /// Read-only counterpart of `update_deps`: the requirement of every
+    /// registry dependency with its locked version, the newest version it
+    /// allows and the newest overall. Returns how many are outdated, that is
+    /// locked behind the compatible version or not allowing the latest.
+    pub async fn outdated<S: AsRef<str>>(
+        &self,
+        pre: bool,
+        rust_version: Option<S>,
+        ct: ColorType,
+    ) -> Result<usize> {
+        self.print_start_msg("OUTDATED DEP(S)");
+
+        let content = Manifest::load(&self.path)?;
+        let msrv = self.msrv(&content, rust_version)?;
+        let msrv = msrv.as_ref();
+        let lock = Self::find_lock(&self.path);
+        let package = content.package_field("name");
+
+        let mut deps = Vec::new();
+        for dtype in content.all_dtypes() {
+            let (fds, _) = Self::update_dep_type(&content, &dtype)?;
+            for d in fds {
+                if self.in_scope(&dtype, &d) {
+                    deps.push((dtype.clone(), d));
+                }
+            }
+        }
+        let futures = deps.iter().map(|(_, d)| async move {
+            let reg = self.registries.get(d.registry())?;
+            reg.fetch(d.crate_name()).await
+        });
+        let fds = future::try_join_all(futures).await?;
+
+        let newer = |a: &str, b: &str| match (Version::parse(a), Version::parse(b)) {
+            (Ok(a), Ok(b)) => a > b,
+            _ => false,
+        };
+        let mut rows = BTreeMap::new();
+        let mut outdated = 0;
+        for ((dtype, d), fd) in deps.into_iter().zip(fds) {
+            let locked = lock
+                .as_ref()
+                .and_then(|l| l.locked_version(package.as_deref(), d.crate_name(), &d.version));
+            let compatible = VersionReq::parse(&d.version)
+                .ok()
+                .and_then(|req| fd.get_last_matching_version(&req, msrv).ok());
+            let latest = fd.get_last_version(pre, msrv).ok();
+
+            let lock_behind = matches!((&compatible, locked), (Some(c), Some(l)) if newer(c, l));
+            let req_behind = matches!((&latest, &compatible), (Some(l), Some(c)) if newer(l, c))
+                || (compatible.is_none() && latest.is_some());
+            if lock_behind || req_behind {
+                outdated += 1;
+            }
+            let cols = [
+                d.version.clone(),
+                locked.unwrap_or("-").to_string(),
+                compatible.unwrap_or("-".to_string()),
+                latest.unwrap_or("-".to_string()),
+            ];
+            rows.entry(dtype)
+                .or_insert(Vec::new())
+                .push((d.name, cols, lock_behind, req_behind));
+        }
+
+        if rows.is_empty() {
+            utils::style::print_description("no registry dependencies");
+        }
+        let mut widths = [4, 11, 6, 10, 6];
+        for (name, cols, _, _) in rows.values().flatten() {
+            widths[0] = widths[0].max(name.len());
+            for (w, c) in widths[1..].iter_mut().zip(cols) {
+                *w = (*w).max(c.len());
+            }
+        }
+        for (t, ds) in rows {
             utils::style::print_cargo_field(&t);
-            for d in ds {
-                utils::style::print_colored_ref_dep_full(&d, mnl, mvl, 2, ct.get_dcolor());
+            utils::style::print_outdated_header(&widths, 2);
+            for (name, cols, lock_behind, req_behind) in ds {
+                utils::style::print_colored_outdated(
+                    name.as_str(),
+                    &cols.each_ref().map(|c| c.as_str()),
+                    &widths,
+                    lock_behind,
+                    req_behind,
+                    2,
+                    ct.get_dcolor(),
+                );
             }
         }
+        if outdated > 0 {
+            utils::style::print_description(format!("{} outdated", outdated));
+        }
 
         utils::style::print_end_msg();
+        Ok(outdated)
+    }
+    /// Registry data of the registry dependencies by `yank_key`. Registry
+    /// errors are only logged, so listing works offline.
+    async fn fetch_known<'a, I: Iterator<Item = &'a Dep>>(
+        &self,
+        deps: I,
+    ) -> HashMap<(Option<String>, String, String), dep::api::CratesDep> {
+        let deps = deps.filter(|d| d.is_updatable()).collect::<Vec<_>>();
+        let futures = deps.iter().map(|d| async move {
+            let reg = self.registries.get(d.registry())?;
+            reg.fetch(d.crate_name()).await
+        });
+
+        let mut res = HashMap::new();
+        for (d, r) in deps.iter().zip(future::join_all(futures).await) {
+            match r {
+                Ok(fd) => {
+                    res.insert(Self::yank_key(d), fd);
+                }
+                Err(e) => info!("can't fetch {}: {}", d.name, e),
+            }
+        }
+        res
+    }
+    /// `Cargo.lock` of the manifest, an unreadable one is reported and
+    /// treated as missing.
+    fn find_lock(path: &Path) -> Option<Lockfile> {
+        Lockfile::find(path).unwrap_or_else(|e| {
+            utils::style::print_warning(0, e.to_string());
+            None
+        })
+    }
+    /// Newest release the requirement allows when the lock is older.
+    fn lock_behind(d: &Dep, fd: &dep::api::CratesDep, locked: &str) -> Option<String> {
+        let req = VersionReq::parse(&d.version).ok()?;
+        let newest = fd.get_last_matching_version(&req, None).ok()?;
+        (Version::parse(&newest).ok()? > Version::parse(locked).ok()?).then_some(newest)
+    }
+    pub fn from_cur(cm: CacheMode) -> Result<Self> {
+        Self::from_path(Self::find_cargo_file(Path::new("."))?, cm)
+    }
+    pub fn from_path(path: PathBuf, cm: CacheMode) -> Result<Self> {
+        let registries = Registries::load(path.parent().unwrap_or(Path::new(".")), cm)?;
+        Ok(Self::with_registries(path, Box::new(registries)))
+    }
+    /// Manifest at `path` resolved against the given registries instead of
+    /// the cargo configuration.
+    pub fn with_registries(path: PathBuf, registries: Box<dyn RegistryProvider>) -> Self {
+        Self {
+            path,
+            registries,
+            member: None,
+            scope: None,
+        }
+    }
+    /// Manifests the selection points at, from the workspace of the one
+    /// found from the current directory. Without flags that is the found
+    /// one, or the default (or all) members in a virtual workspace root.
+    pub fn select(cm: CacheMode, sel: &Selection) -> Result<Vec<Self>> {
+        Self::select_in(Path::new("."), cm, sel)
+    }
+    /// `select` starting from `dir` instead of the current directory.
+    fn select_in(dir: &Path, cm: CacheMode, sel: &Selection) -> Result<Vec<Self>> {
+        let cur = fs::canonicalize(Self::find_cargo_file(dir)?)?;
+        let Some(ws) = Workspace::find(&cur)? else {
+            let name = Manifest::load(&cur)?.package_field("name");
+            if let Some(p) = sel.packages.iter().find(|p| Some(*p) != name.as_ref()) {
+                return Err(anyhow!("package {} not found, not in a workspace", p));
+            }
+            return Ok(vec![Self::from_path(cur, cm)?]);
+        };
+
+        let mut paths = Vec::new();
+        if !sel.packages.is_empty() {
+            for p in &sel.packages {
+                let m = ws
+                    .member(p)
+                    .ok_or(anyhow!("package {} is not a member of the workspace", p))?;
+                paths.push(m.path.clone());
+            }
+        } else if sel.workspace || sel.all {
+            paths.extend(ws.members.iter().map(|m| m.path.clone()));
+            if sel.all && ws.is_virtual {
+                paths.push(ws.root.clone());
+            }
+        } else if cur == ws.root && ws.is_virtual {
+            paths = match ws.default_members.is_empty() {
+                true => ws.members.iter().map(|m| m.path.clone()).collect(),
+                false => ws.default_members.clone(),
+            };
+        } else {
+            paths.push(cur);
+        }
+
+        let mut res = Vec::new();
+        for path in paths {
+            let member = match ws.members.iter().find(|m| m.path == path) {
+                Some(m) => m.name.clone(),
+                None => WORKSPACE_ROOT.to_string(),
+            };
+            let mut cp = Self::from_path(path, cm)?;
+            cp.member = Some(member);
+            res.push(cp);
+        }
+        Ok(res)
+    }
+    /// The workspace root, limited to the `[workspace.dependencies]` the
+    /// manifests inherit, unless the root is one of them already. Updating
+    /// it bumps each inherited entry once instead of in every member.
+    pub fn inherited_root(cm: CacheMode, cps: &[Self]) -> Result<Option<Self>> {
+        let Some(first) = cps.first() else {
+            return Ok(None);
+        };
+        let Some(ws) = Workspace::find(&first.path)? else {
+            return Ok(None);
+        };
+        if cps.iter().any(|cp| cp.path == ws.root) {
+            return Ok(None);
+        }
+
+        let mut names = HashSet::new();
+        for cp in cps {
+            let content = Manifest::load(&cp.path)?;
+            for dtype in content.dtypes() {
+                for d in content.get_deps(&dtype)? {
+                    if d.source == DSource::Workspace {
+                        names.insert(d.name);
+                    }
+                }
+            }
+        }
+        if names.is_empty() {
+            return Ok(None);
+        }
+        let mut root = Self::from_path(ws.root, cm)?;
+        root.member = Some(WORKSPACE_ROOT.to_string());
+        root.scope = Some((DType::Workspace, names));
+        Ok(Some(root))
+    }
+    fn in_scope(&self, dtype: &DType, d: &Dep) -> bool {
+        match &self.scope {
+            Some((t, names)) => t == dtype && names.contains(&d.name),
+            None => true,
+        }
+    }
+    /// Member entry inheriting the dependency from the workspace, with the
+    /// only keys cargo allows next to `workspace = true`: `features` and
+    /// `optional`.
+    fn inheriting(d: &Dep) -> Dep {
+        Dep {
+            name: d.name.clone(),
+            source: DSource::Workspace,
+            features: d.features.clone(),
+            optional: d.optional,
+            ..Default::default()
+        }
+    }
+    /// Virtual workspace roots have no dependency tables to add to.
+    pub fn check_not_virtual(&self) -> Result<()> {
+        if Manifest::load(&self.path)?.is_virtual() {
+            return Err(anyhow!(
+                "{} is a virtual manifest, pick members with --package or --workspace",
+                self.path.display()
+            ));
+        }
         Ok(())
     }
-    pub fn from_cur() -> Result<Self> {
-        let cf = Self::find_cargo_file(Path::new("."))?;
-        Ok(Self(cf))
+    /// Header of a command, naming the member inside a workspace.
+    fn print_start_msg(&self, name: &str) {
+        utils::style::print_start_msg_for(name, self.member.as_deref());
     }
+    /// Closest `Cargo.toml` in the directory or above it.
     fn find_cargo_file<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
-        let path = path.as_ref();
-        let files = fs::read_dir(path)?;
-        for file in files.into_iter().flatten() {
-            if file.path().display().to_string().contains("Cargo.toml") {
-                return Ok(file.path());
+        let mut dir = Some(fs::canonicalize(path.as_ref())?);
+        while let Some(d) = dir {
+            let path = d.join("Cargo.toml");
+            if path.is_file() {
+                return Ok(path);
             }
-        }
-        if let Some(parent) = path.parent() {
-            return Self::find_cargo_file(parent);
+            dir = d.parent().map(|p| p.to_path_buf());
         }
         Err(anyhow!("cargo not found"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{self, AtomicUsize};
+    use std::{env, process};
+
+    use super::*;
+    use crate::registry::MemoryRegistry;
+
+    const FIXTURE: &str = r#"{
+        "serde": [
+            {"vers": "1.0.100", "rust_version": "1.60"},
+            {"vers": "1.0.200", "rust_version": "1.80", "features": {"default": ["std"], "std": [], "derive": []}}
+        ],
+        "tokio": [
+            {"vers": "1.30.0"},
+            {"vers": "1.40.0", "features": {"default": ["rt"], "rt": [], "full": ["rt", "net"], "net": []}}
+        ],
+        "serde_json": [{"vers": "1.0.0"}, {"vers": "1.0.140"}],
+        "json": [{"vers": "0.12.4"}]
+    }"#;
+
+    /// Manifest in a directory of its own, so tests can run in parallel,
+    /// resolved against `FIXTURE`. The directory goes away with it.
+    struct Project(Cargo);
+
+    impl std::ops::Deref for Project {
+        type Target = Cargo;
+        fn deref(&self) -> &Cargo {
+            &self.0
+        }
+    }
+    impl Drop for Project {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.path.parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    fn project(manifest: &str) -> Project {
+        static N: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::temp_dir().join(format!(
+            "depi-test-{}-{}",
+            process::id(),
+            N.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Cargo.toml");
+        fs::write(&path, manifest).unwrap();
+        let reg = MemoryRegistry::from_json(FIXTURE).unwrap();
+        Project(Cargo::with_registries(path, Box::new(reg)))
+    }
+    /// Workspace with the root manifest and members in `<dir>/Cargo.toml`.
+    fn workspace(root: &str, members: &[(&str, &str)]) -> Project {
+        let ws = project(root);
+        for (dir, manifest) in members {
+            let dir = ws.path.parent().unwrap().join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        }
+        ws
+    }
+    fn member(ws: &Cargo, dir: &str) -> Cargo {
+        let path = ws.path.parent().unwrap().join(dir).join("Cargo.toml");
+        let reg = MemoryRegistry::from_json(FIXTURE).unwrap();
+        Cargo::with_registries(path, Box::new(reg))
+    }
+    fn read(cp: &Cargo) -> String {
+        fs::read_to_string(&cp.path).unwrap()
+    }
+    fn package(name: &str, deps: &str) -> String {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+            name, deps
+        )
+    }
+
+    const PACKAGE: &str = "[package]\nname = \"p\"\nversion = \"0.1.0\"\n";
+
+    #[tokio::test]
+    async fn add_picks_newest_version() {
+        let cp = project(&format!("{}\n[dependencies]\n", PACKAGE));
+        cp.append_deps(
+            "serde:derive",
+            None,
+            false,
+            None,
+            false,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert!(read(&cp).contains(r#"serde = { version = "1.0.200", features = ["derive"] }"#));
+    }
+
+    #[tokio::test]
+    async fn add_respects_rust_version() {
+        let cp = project(&format!("{}\n[dependencies]\n", PACKAGE));
+        cp.append_deps(
+            "serde",
+            None,
+            false,
+            Some("1.70"),
+            false,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert!(read(&cp).contains(r#"serde = "1.0.100""#));
+    }
+
+    #[tokio::test]
+    async fn add_respects_inherited_rust_version() {
+        let ws = workspace(
+            "[workspace]\nmembers = [\"p\"]\n\n[workspace.package]\nrust-version = \"1.70\"\n",
+            &[(
+                "p",
+                "[package]\nname = \"p\"\nversion = \"0.1.0\"\nrust-version.workspace = true\n\n[dependencies]\n",
+            )],
+        );
+        let cp = member(&ws, "p");
+        cp.append_deps("serde", None, false, None, false, ColorType::default())
+            .await
+            .unwrap();
+        assert!(read(&cp).contains(r#"serde = "1.0.100""#));
+    }
+
+    #[tokio::test]
+    async fn add_rejects_unknown_feature() {
+        let cp = project(&format!("{}\n[dependencies]\n", PACKAGE));
+        let res = cp
+            .append_deps("tokio:nope", None, false, None, false, ColorType::default())
+            .await;
+        assert!(res.is_err());
+        assert!(!read(&cp).contains("tokio"));
+    }
+
+    #[tokio::test]
+    async fn update_keeps_formatting() {
+        let cp = project(&format!(
+            "{}\n[dependencies]\n# comment\nserde = \"1.0.100\" # pinned\ntokio = {{ version = \"1.30.0\", features = [\"rt\"] }}\n",
+            PACKAGE
+        ));
+        cp.update_deps(
+            UpdatePolicy::Latest,
+            false,
+            None::<&str>,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        let m = read(&cp);
+        assert!(m.contains("# comment\nserde = \"1.0.200\" # pinned\n"));
+        assert!(m.contains(r#"tokio = { version = "1.40.0", features = ["rt"] }"#));
+    }
+
+    #[tokio::test]
+    async fn update_compatible_stays_in_range() {
+        let cp = project(&format!(
+            "{}\n[dependencies]\nserde_json = \"=1.0.0\"\ntokio = \"1.30\"\n",
+            PACKAGE
+        ));
+        cp.update_deps(
+            UpdatePolicy::Compatible,
+            false,
+            None::<&str>,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        let m = read(&cp);
+        assert!(m.contains(r#"serde_json = "=1.0.0""#));
+        assert!(m.contains(r#"tokio = "1.40.0""#));
+    }
+
+    #[tokio::test]
+    async fn update_latest_replaces_unmovable_requirements() {
+        let manifest = format!(
+            "{}\n[dependencies]\nserde = \">=1.0, <1.0.150\"\ntokio = \"*\"\njson = \"0.12\"\n",
+            PACKAGE
+        );
+        let cp = project(&manifest);
+        cp.update_deps(
+            UpdatePolicy::Latest,
+            false,
+            None::<&str>,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(read(&cp), manifest.replace("0.12\"", "0.12.4\""));
+
+        cp.update_deps(
+            UpdatePolicy::Replace,
+            false,
+            None::<&str>,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            read(&cp),
+            format!(
+                "{}\n[dependencies]\nserde = \"1.0.200\"\ntokio = \"1.40.0\"\njson = \"0.12.4\"\n",
+                PACKAGE
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn update_renamed_dep_uses_package() {
+        let cp = project(&format!(
+            "{}\n[dependencies]\njson = {{ package = \"serde_json\", version = \"1.0.0\" }}\n",
+            PACKAGE
+        ));
+        cp.update_deps(
+            UpdatePolicy::Latest,
+            false,
+            None::<&str>,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert!(read(&cp).contains(r#"json = { package = "serde_json", version = "1.0.140" }"#));
+    }
+
+    #[tokio::test]
+    async fn feature_add_and_remove_round_trip() {
+        let manifest = format!("{}\n[dependencies]\ntokio = \"1.40.0\" # rt\n", PACKAGE);
+        let cp = project(&manifest);
+        cp.add_features("tokio", "full,net", ColorType::default())
+            .await
+            .unwrap();
+        assert!(
+            read(&cp)
+                .contains(r#"tokio = { version = "1.40.0", features = ["full", "net"] } # rt"#)
+        );
+
+        assert!(
+            cp.add_features("tokio", "nope", ColorType::default())
+                .await
+                .is_err()
+        );
+        cp.remove_features("tokio", "full,net", ColorType::default())
+            .await
+            .unwrap();
+        assert_eq!(read(&cp), manifest);
+    }
+
+    #[tokio::test]
+    async fn features_of_the_locked_or_pinned_version() {
+        let cp = project(&package("p", "serde = \"1.0.100\"\n"));
+        let lock = cp.path.with_file_name("Cargo.lock");
+        fs::write(
+            &lock,
+            "version = 4\n\n[[package]]\nname = \"p\"\nversion = \"0.1.0\"\ndependencies = [\"serde\"]\n\n\
+             [[package]]\nname = \"serde\"\nversion = \"1.0.100\"\n\
+             source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+        )
+        .unwrap();
+        let err = cp
+            .add_features("serde", "derive", ColorType::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "serde 1.0.100 has no feature derive");
+
+        fs::remove_file(lock).unwrap();
+        cp.add_features("serde", "derive", ColorType::default())
+            .await
+            .unwrap();
+
+        let cp = project(&package("p", "serde = \"=1.0.100\"\n"));
+        let err = cp
+            .add_features("serde", "derive", ColorType::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "serde 1.0.100 has no feature derive");
+    }
+
+    #[tokio::test]
+    async fn features_of_inherited_entries() {
+        let ws = workspace(
+            &format!(
+                "{}\n[workspace.dependencies]\nserde = \"=1.0.100\"\ntokio = \"1.30\"\n",
+                WS_AB
+            ),
+            &[(
+                "a",
+                &package(
+                    "a",
+                    "serde = { workspace = true }\ntokio = { workspace = true }\n",
+                ),
+            )],
+        );
+        let a = member(&ws, "a");
+        let err = a
+            .add_features("serde", "derive", ColorType::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "serde 1.0.100 has no feature derive");
+
+        a.add_features("tokio", "full", ColorType::default())
+            .await
+            .unwrap();
+        assert!(read(&a).contains(r#"tokio = { workspace = true, features = ["full"] }"#));
+        let err = a
+            .add_features("tokio", "nope", ColorType::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "tokio 1.40.0 has no feature nope");
+        a.list_features("tokio", ColorType::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_keeps_inherited_workspace_entries() {
+        let ws = workspace(
+            &format!(
+                "{}\n[workspace]\nmembers = [\"a\"]\n\n[workspace.dependencies]\nserde = \"1.0.200\"\n",
+                package("root", "serde = { workspace = true }\n")
+            ),
+            &[("a", &package("a", "serde = { workspace = true }\n"))],
+        );
+        ws.remove_deps("serde", false, ColorType::default())
+            .await
+            .unwrap();
+        let root = read(&ws);
+        assert!(!root.contains("[dependencies]"));
+        assert!(root.contains("[workspace.dependencies]\nserde = \"1.0.200\"\n"));
+
+        // the root entry only goes when asked for and nothing inherits it
+        let err = ws
+            .remove_deps("serde", true, ColorType::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("still inherited by a"), "{}", err);
+        assert_eq!(read(&ws), root);
+
+        let a = member(&ws, "a");
+        a.remove_deps("serde", true, ColorType::default())
+            .await
+            .unwrap();
+        assert!(!read(&a).contains("serde"));
+        assert!(!read(&ws).contains("serde"));
+    }
+
+    const WS_AB: &str = "[workspace]\nmembers = [\"a\", \"b\"]\n";
+
+    #[test]
+    fn hoist_moves_other_keys_to_the_root() {
+        let entry = "serde = { version = \"1.0.200\", features = [\"derive\"], public = true }\n";
+        let ws = workspace(
+            WS_AB,
+            &[("a", &package("a", entry)), ("b", &package("b", entry))],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert!(read(&ws).contains(
+            "[workspace.dependencies]\nserde = { version = \"1.0.200\", public = true }\n"
+        ));
+        for m in ["a", "b"] {
+            assert!(
+                read(&member(&ws, m))
+                    .contains("serde = { features = [\"derive\"], workspace = true }\n")
+            );
+        }
+
+        let ws = workspace(
+            WS_AB,
+            &[
+                ("a", &package("a", entry)),
+                ("b", &package("b", "serde = \"1.0.200\"\n")),
+            ],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert!(!read(&ws).contains("serde"));
+        assert!(read(&member(&ws, "a")).contains(entry));
+    }
+
+    #[test]
+    fn hoist_takes_the_highest_requirement() {
+        let ws = workspace(
+            WS_AB,
+            &[
+                (
+                    "a",
+                    &package("a", "serde = \"1.0.100\"\ntokio = \"1.30\"\n"),
+                ),
+                ("b", &package("b", "serde = \"1.0.200\"\njson = \"0.12\"\n")),
+            ],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert!(read(&ws).contains("[workspace.dependencies]\nserde = \"1.0.200\"\n"));
+        // only one member has them
+        assert!(!read(&ws).contains("tokio"));
+        assert!(read(&member(&ws, "a")).contains("tokio = \"1.30\""));
+        assert!(read(&member(&ws, "b")).contains("json = \"0.12\""));
+        for m in ["a", "b"] {
+            assert!(read(&member(&ws, m)).contains("serde = { workspace = true }\n"));
+        }
+    }
+
+    #[test]
+    fn hoist_keeps_a_newer_root_entry_only() {
+        let members = [
+            ("a", package("a", "serde = \"1.0.200\"\n")),
+            ("b", package("b", "serde = \"1.0.100\"\n")),
+        ];
+        let members = members
+            .iter()
+            .map(|(d, m)| (*d, m.as_str()))
+            .collect::<Vec<_>>();
+
+        let root = format!("{}\n[workspace.dependencies]\nserde = \"1.0.100\"\n", WS_AB);
+        let ws = workspace(&root, &members);
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert_eq!(read(&ws), root);
+        assert!(read(&member(&ws, "a")).contains("serde = \"1.0.200\""));
+
+        let root = format!("{}\n[workspace.dependencies]\nserde = \"1.0.210\"\n", WS_AB);
+        let ws = workspace(&root, &members);
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert_eq!(read(&ws), root);
+        assert!(read(&member(&ws, "b")).contains("serde = { workspace = true }"));
+    }
+
+    #[test]
+    fn hoist_keeps_member_features_and_optional() {
+        let ws = workspace(
+            WS_AB,
+            &[
+                (
+                    "a",
+                    &package(
+                        "a",
+                        "tokio = { version = \"1.40\", features = [\"rt\"], optional = true }\n",
+                    ),
+                ),
+                (
+                    "b",
+                    &package(
+                        "b",
+                        "tokio = { version = \"1.40\", features = [\"net\"] }\n",
+                    ),
+                ),
+            ],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert!(read(&ws).contains("[workspace.dependencies]\ntokio = \"1.40\"\n"));
+        assert!(
+            read(&member(&ws, "a"))
+                .contains("tokio = { features = [\"rt\"], optional = true, workspace = true }\n")
+        );
+        assert!(
+            read(&member(&ws, "b"))
+                .contains("tokio = { features = [\"net\"], workspace = true }\n")
+        );
+    }
+
+    #[test]
+    fn hoist_keeps_default_features_off() {
+        let off = [
+            "serde = { version = \"1.0.200\", default-features = false }\n",
+            "serde = { version = \"1.0.200\", default_features = false }\n",
+        ];
+        let ws = workspace(
+            WS_AB,
+            &[("a", &package("a", off[0])), ("b", &package("b", off[1]))],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert!(read(&ws).contains(
+            "[workspace.dependencies]\nserde = { version = \"1.0.200\", default-features = false }\n"
+        ));
+        for m in ["a", "b"] {
+            assert!(read(&member(&ws, m)).contains("serde = { workspace = true }\n"));
+        }
+
+        // turning them back on in some members only can't be inherited
+        let ws = workspace(
+            WS_AB,
+            &[
+                ("a", &package("a", off[1])),
+                ("b", &package("b", "serde = \"1.0.200\"\n")),
+            ],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        assert!(!read(&ws).contains("serde"));
+        assert!(read(&member(&ws, "a")).contains(off[1]));
+    }
+
+    #[test]
+    fn hoist_into_a_root_package() {
+        let ws = workspace(
+            &format!(
+                "{}\n[workspace]\nmembers = [\"a\"]\n",
+                package("root", "serde = \"1.0.100\"\n")
+            ),
+            &[("a", &package("a", "serde = \"1.0.200\"\n"))],
+        );
+        ws.hoist(None::<&str>, ColorType::default()).unwrap();
+        let root = read(&ws);
+        assert!(root.contains("[dependencies]\nserde = { workspace = true }\n"));
+        assert!(root.contains("[workspace.dependencies]\nserde = \"1.0.200\"\n"));
+        assert!(read(&member(&ws, "a")).contains("serde = { workspace = true }\n"));
+    }
+
+    #[tokio::test]
+    async fn add_workspace_dep() {
+        let ws = workspace(
+            &format!("{}\n[workspace.dependencies]\nserde = \"1.0.100\"\n", WS_AB),
+            &[("a", &package("a", "")), ("b", &package("b", ""))],
+        );
+        let a = member(&ws, "a");
+        a.append_deps("tokio:full", None, false, None, true, ColorType::default())
+            .await
+            .unwrap();
+        assert!(read(&a).contains("tokio = { workspace = true, features = [\"full\"] }\n"));
+        assert!(read(&ws).contains("tokio = \"1.40.0\"\n"));
+
+        // the root entry is kept as is
+        let b = member(&ws, "b");
+        b.append_deps("serde", None, false, None, true, ColorType::default())
+            .await
+            .unwrap();
+        assert!(read(&b).contains("serde = { workspace = true }\n"));
+        assert!(read(&ws).contains("serde = \"1.0.100\"\n"));
+    }
+
+    #[tokio::test]
+    async fn target_tables_through_add_update_and_remove() {
+        let cp = project(&format!(
+            "{}\n[dependencies]\nserde = \"1.0.200\"\n\n[target.'cfg(windows)'.dependencies]\ntokio = {{ version = \"1.30.0\", features = [\"rt\"] }}\n",
+            PACKAGE
+        ));
+        cp.append_deps(
+            "serde_json!cfg(any(unix,windows))",
+            None,
+            false,
+            None,
+            false,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert!(read(&cp).ends_with(
+            "\n[target.'cfg(any(unix, windows))'.dependencies]\nserde_json = \"1.0.140\"\n"
+        ));
+
+        cp.update_deps(
+            UpdatePolicy::Latest,
+            false,
+            None::<&str>,
+            ColorType::default(),
+        )
+        .await
+        .unwrap();
+        assert!(read(&cp).contains(
+            "[target.'cfg(windows)'.dependencies]\ntokio = { version = \"1.40.0\", features = [\"rt\"] }\n"
+        ));
+
+        cp.remove_deps("tokio,serde_json", false, ColorType::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            read(&cp),
+            format!("{}\n[dependencies]\nserde = \"1.0.200\"\n", PACKAGE)
+        );
+    }
+
+    #[test]
+    fn select_packages() {
+        let ws = workspace(
+            "[workspace]\nmembers = [\"a\", \"b\"]\ndefault-members = [\"b\"]\n",
+            &[("a", &package("a", "")), ("b", &package("b", ""))],
+        );
+        let dir = ws.path.parent().unwrap();
+        let select = |from: &Path, packages: &[&str], workspace: bool, all: bool| {
+            let sel = Selection {
+                packages: packages.iter().map(|p| p.to_string()).collect(),
+                workspace,
+                all,
+            };
+            Cargo::select_in(from, CacheMode::Offline, &sel).map(|cps| {
+                cps.into_iter()
+                    .map(|cp| cp.member.unwrap_or_default())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(select(dir, &[], false, false).unwrap(), ["b"]);
+        assert_eq!(select(&dir.join("a"), &[], false, false).unwrap(), ["a"]);
+        assert_eq!(select(dir, &["a"], false, false).unwrap(), ["a"]);
+        assert_eq!(select(dir, &["b", "a"], false, false).unwrap(), ["b", "a"]);
+        assert_eq!(select(dir, &[], true, false).unwrap(), ["a", "b"]);
+        assert_eq!(
+            select(&dir.join("a"), &[], false, true).unwrap(),
+            ["a", "b", WORKSPACE_ROOT]
+        );
+        let err = select(dir, &["nope"], false, false).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("package nope is not a member of the workspace"),
+            "{}",
+            err
+        );
+
+        let cp = project(PACKAGE);
+        let dir = cp.path.parent().unwrap();
+        assert_eq!(select(dir, &["p"], false, false).unwrap().len(), 1);
+        let err = select(dir, &["a"], false, false).unwrap_err();
+        assert!(err.to_string().contains("not in a workspace"), "{}", err);
+    }
+
+    const WS_ABC: &str = "[workspace]\nmembers = [\"a\", \"b\", \"c\"]\n";
+
+    #[test]
+    fn consistency_only_reports_without_unify() {
+        let (a, b) = (
+            package("a", "serde = \"1.0.200\"\n"),
+            package("b", "serde = \"1.0.100\"\n"),
+        );
+        let ws = workspace(WS_AB, &[("a", &a), ("b", &b)]);
+        ws.consistency(false, ColorType::default()).unwrap();
+        assert_eq!(read(&member(&ws, "a")), a);
+        assert_eq!(read(&member(&ws, "b")), b);
+
+        let cp = project(PACKAGE);
+        let err = cp.consistency(false, ColorType::default()).unwrap_err();
+        assert!(err.to_string().contains("is not in a workspace"), "{}", err);
+    }
+
+    #[test]
+    fn consistency_unifies_requirements() {
+        let c = format!(
+            "{}\n[dev-dependencies]\ntokio = \"1.30\"\n",
+            package("c", "json = \"0.12\"\n")
+        );
+        let ws = workspace(
+            WS_ABC,
+            &[
+                (
+                    "a",
+                    &package("a", "tokio = { version = \"1.30\", features = [\"rt\"] }\n"),
+                ),
+                ("b", &package("b", "tokio = \"1.40\"\n")),
+                ("c", &c),
+            ],
+        );
+        ws.consistency(true, ColorType::default()).unwrap();
+        assert_eq!(
+            read(&member(&ws, "a")),
+            package("a", "tokio = { version = \"1.40\", features = [\"rt\"] }\n")
+        );
+        assert_eq!(read(&member(&ws, "b")), package("b", "tokio = \"1.40\"\n"));
+        assert_eq!(
+            read(&member(&ws, "c")),
+            c.replace("tokio = \"1.30\"", "tokio = \"1.40\"")
+        );
+    }
+
+    #[test]
+    fn consistency_raises_inherited_entries_in_the_root() {
+        let root = format!(
+            "{}\n[workspace.dependencies]\nserde = \"1.0.100\"\ntokio = \"1.40\"\n",
+            WS_ABC
+        );
+        let (a, b, c) = (
+            package(
+                "a",
+                "serde = { workspace = true }\ntokio = { workspace = true }\n",
+            ),
+            package(
+                "b",
+                "serde = { workspace = true, features = [\"derive\"] }\n",
+            ),
+            package("c", "serde = \"1.0.200\"\ntokio = \"1.30\"\n"),
+        );
+        let ws = workspace(&root, &[("a", &a), ("b", &b), ("c", &c)]);
+
+        // inherited entries are left alone in the members when only reporting
+        ws.consistency(false, ColorType::default()).unwrap();
+        assert_eq!(read(&ws), root);
+
+        ws.consistency(true, ColorType::default()).unwrap();
+        // serde is raised once, in the root, and the members keep inheriting it
+        assert_eq!(read(&ws), root.replace("1.0.100", "1.0.200"));
+        assert_eq!(read(&member(&ws, "a")), a);
+        assert_eq!(read(&member(&ws, "b")), b);
+        // the inherited tokio is already the highest, the member catches up
+        assert_eq!(
+            read(&member(&ws, "c")),
+            package("c", "serde = \"1.0.200\"\ntokio = \"1.40\"\n")
+        );
+    }
+}
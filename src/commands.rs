@@ -1,12 +1,15 @@
 use std::path::PathBuf;
 use std::{fs, io::Write, process};
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Result, anyhow};
+use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
 
+use crate::dep::UpdatePolicy;
+use crate::registry::{CacheMode, Registries, RegistryProvider};
 use crate::utils::ColorType;
-use crate::{cargo, storage};
+use crate::workspace::Selection;
+use crate::{cargo, storage, utils};
 
 const MAIN: &str = r#"
 fn main() {
@@ -14,6 +17,11 @@ fn main() {
 }
 "#;
 
+const REGISTRY_HELP: &str = "Registry from the cargo config to use instead of crates.io. Its index \
+                             must be a sparse one or a local checkout of a git index (`file://`)";
+const RUST_VERSION_HELP: &str =
+    "Only consider versions building with this rust, defaults to `package.rust-version`";
+
 #[derive(Debug, Parser)]
 #[clap(about = "Dependencies Manager for Rust Projects", version)]
 enum DepiCommand {
@@ -21,6 +29,11 @@ enum DepiCommand {
         #[clap(short = 'D', long)]
         deps: Option<String>,
 
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
         #[clap(short, long, default_value = "osetia")]
         color: ColorType,
     },
@@ -30,6 +43,11 @@ enum DepiCommand {
 
         #[clap(short = 'D', long)]
         deps: Option<String>,
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
         #[clap(short, long, default_value = "osetia")]
         color: ColorType,
     },
@@ -37,6 +55,45 @@ enum DepiCommand {
         #[clap(required = true)]
         deps: String,
 
+        #[clap(long, help = REGISTRY_HELP)]
+        registry: Option<String>,
+
+        #[clap(long)]
+        pre: bool,
+        #[clap(long, help = RUST_VERSION_HELP)]
+        rust_version: Option<String>,
+        /// Write the version into `[workspace.dependencies]` of the root and
+        /// inherit it with `{ workspace = true }`.
+        #[clap(long)]
+        workspace_dep: bool,
+
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+
+        #[clap(flatten)]
+        sel: PackageArgs,
+    },
+    /// Move dependencies several workspace members declare into
+    /// `[workspace.dependencies]`.
+    Hoist {
+        /// Comma separated, hoisted even if only one member has them.
+        names: Option<String>,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+    },
+    /// Show crates the workspace members require at different versions or
+    /// with different features.
+    Consistency {
+        /// Raise the lower requirements to the highest one.
+        #[clap(long)]
+        unify: bool,
+
         #[clap(short, long, default_value = "osetia")]
         color: ColorType,
     },
@@ -44,16 +101,115 @@ enum DepiCommand {
         #[clap(required = true)]
         names: String,
 
+        /// Also remove them from `[workspace.dependencies]` of the root,
+        /// refused while other members still inherit them.
+        #[clap(long)]
+        workspace_dep: bool,
+
         #[clap(short, long, default_value = "osetia")]
         color: ColorType,
+
+        #[clap(flatten)]
+        sel: PackageArgs,
     },
     List {
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
         #[clap(short, long, default_value = "osetia")]
         color: ColorType,
+
+        #[clap(flatten)]
+        sel: PackageArgs,
     },
     Update {
+        #[clap(long, conflicts_with_all = ["minor", "patch", "latest"])]
+        compatible: bool,
+        #[clap(long, conflicts_with_all = ["patch", "latest"])]
+        minor: bool,
+        #[clap(long, conflicts_with = "latest")]
+        patch: bool,
+        /// Also replace requirements which cannot be moved, like `*` or
+        /// `>=1, <2`, with the newest version.
+        #[clap(long)]
+        latest: bool,
+
+        #[clap(long)]
+        pre: bool,
+        #[clap(long, help = RUST_VERSION_HELP)]
+        rust_version: Option<String>,
+
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
         #[clap(short, long, default_value = "osetia")]
         color: ColorType,
+
+        #[clap(flatten)]
+        sel: PackageArgs,
+    },
+    /// Show requirements behind the newest versions without changing
+    /// anything, fails if any are.
+    Outdated {
+        #[clap(long)]
+        pre: bool,
+        #[clap(long, help = RUST_VERSION_HELP)]
+        rust_version: Option<String>,
+
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+
+        #[clap(flatten)]
+        sel: PackageArgs,
+    },
+
+    Search {
+        #[clap(required = true)]
+        query: String,
+
+        /// Page of the results, starting at 1.
+        #[clap(short, long, default_value_t = 1)]
+        page: usize,
+        #[clap(long, help = REGISTRY_HELP)]
+        registry: Option<String>,
+        /// Ask for one of the results by its number and add it to the
+        /// project.
+        #[clap(long)]
+        add: bool,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+    },
+
+    Info {
+        /// Crate, with `@version` to show another than the newest.
+        #[clap(required = true)]
+        dep: String,
+
+        #[clap(long, help = REGISTRY_HELP)]
+        registry: Option<String>,
+
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+    },
+
+    Feature {
+        #[clap(subcommand)]
+        command: FeatureCommand,
     },
 
     Alias {
@@ -62,6 +218,71 @@ enum DepiCommand {
     },
 }
 
+// Which workspace members a command works on.
+#[derive(Args, Debug)]
+struct PackageArgs {
+    /// Workspace member to work on, can be repeated.
+    #[clap(short, long = "package")]
+    packages: Vec<String>,
+    /// Every workspace member.
+    #[clap(long, conflicts_with = "packages")]
+    workspace: bool,
+    /// Every workspace member and the workspace root.
+    #[clap(long, conflicts_with_all = ["packages", "workspace"])]
+    all: bool,
+}
+
+impl From<PackageArgs> for Selection {
+    fn from(pa: PackageArgs) -> Self {
+        Self {
+            packages: pa.packages,
+            workspace: pa.workspace,
+            all: pa.all,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum FeatureCommand {
+    Add {
+        #[clap(required = true)]
+        dep: String,
+        /// Comma separated, checked against the features of the version
+        /// the requirement resolves to.
+        #[clap(required = true)]
+        features: String,
+
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+    },
+    Remove {
+        #[clap(required = true)]
+        dep: String,
+        #[clap(required = true)]
+        features: String,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+    },
+    List {
+        #[clap(required = true)]
+        dep: String,
+
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        #[clap(long)]
+        refresh: bool,
+
+        #[clap(short, long, default_value = "osetia")]
+        color: ColorType,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum AliasCommand {
     Add {
@@ -80,9 +301,15 @@ enum AliasCommand {
 pub async fn handle_command() -> Result<()> {
     let com = DepiCommand::parse();
     match com {
-        DepiCommand::Init { deps, color } => {
+        DepiCommand::Init {
+            deps,
+            offline,
+            refresh,
+            color,
+        } => {
             // let cp = Cargo::from_cur()?;
-            let cs = cargo::Cargo::init_project(None, deps.as_deref(), color).await?;
+            let regs = Registries::load(".", CacheMode::from_flags(offline, refresh))?;
+            let cs = cargo::Cargo::init_project(None, deps.as_deref(), &regs, color).await?;
 
             let mut f = fs::File::create("Cargo.toml")?;
             f.write_all(cs.as_bytes())?;
@@ -94,19 +321,26 @@ pub async fn handle_command() -> Result<()> {
             let gout = process::Command::new("git").arg("init").output()?.stdout;
             println!("{}", String::from_utf8(gout)?.bold());
         }
-        DepiCommand::New { name, deps, color } => {
-            let cs =
-                cargo::Cargo::init_project(Some(name.as_ref()), deps.as_deref(), color).await?;
+        DepiCommand::New {
+            name,
+            deps,
+            offline,
+            refresh,
+            color,
+        } => {
+            let regs = Registries::load(".", CacheMode::from_flags(offline, refresh))?;
+            let cs = cargo::Cargo::init_project(Some(name.as_ref()), deps.as_deref(), &regs, color)
+                .await?;
 
             let name = PathBuf::from(name);
             fs::create_dir(&name)?;
 
-            let mut f = fs::File::create(&name.join("Cargo.toml"))?;
+            let mut f = fs::File::create(name.join("Cargo.toml"))?;
             f.write_all(cs.as_bytes())?;
 
             let src = name.join("src");
             fs::create_dir(&src)?;
-            let mut f = fs::File::create(&src.join("main.rs"))?;
+            let mut f = fs::File::create(src.join("main.rs"))?;
             f.write_all(MAIN.as_bytes())?;
 
             let gout = process::Command::new("git")
@@ -116,22 +350,193 @@ pub async fn handle_command() -> Result<()> {
                 .stdout;
             println!("{}", String::from_utf8(gout)?.bold());
         }
-        DepiCommand::Add { deps, color } => {
-            let cp = cargo::Cargo::from_cur()?;
-            cp.append_deps(deps, color).await?;
+        DepiCommand::Add {
+            deps,
+            registry,
+            pre,
+            rust_version,
+            workspace_dep,
+            offline,
+            refresh,
+            color,
+            sel,
+        } => {
+            let sel = Selection::from(sel);
+            let explicit = sel.workspace || sel.all || !sel.packages.is_empty();
+            let cps = cargo::Cargo::select(CacheMode::from_flags(offline, refresh), &sel)?;
+            if !explicit && cps.len() > 1 {
+                return Err(anyhow!(
+                    "the workspace root is a virtual manifest, pick members with --package or --workspace"
+                ));
+            }
+            for cp in &cps {
+                cp.check_not_virtual()?;
+            }
+            for cp in cps {
+                cp.append_deps(
+                    &deps,
+                    registry.as_ref(),
+                    pre,
+                    rust_version.as_ref(),
+                    workspace_dep,
+                    color.clone(),
+                )
+                .await?;
+            }
+        }
+        DepiCommand::Hoist { names, color } => {
+            let cp = cargo::Cargo::from_cur(CacheMode::default())?;
+            cp.hoist(names, color)?;
+        }
+        DepiCommand::Consistency { unify, color } => {
+            let cp = cargo::Cargo::from_cur(CacheMode::default())?;
+            cp.consistency(unify, color)?;
+        }
+        DepiCommand::Remove {
+            names,
+            workspace_dep,
+            color,
+            sel,
+        } => {
+            let cps = cargo::Cargo::select(CacheMode::default(), &sel.into())?;
+            // the root entries go last, once the selected members stopped
+            // inheriting them
+            let last = cps.len().saturating_sub(1);
+            for (i, cp) in cps.iter().enumerate() {
+                cp.remove_deps(&names, workspace_dep && i == last, color.clone())
+                    .await?;
+            }
+        }
+        DepiCommand::Update {
+            compatible,
+            minor,
+            patch,
+            latest,
+            pre,
+            rust_version,
+            offline,
+            refresh,
+            color,
+            sel,
+        } => {
+            let policy = UpdatePolicy::from_flags(compatible, minor, patch, latest);
+            let cm = CacheMode::from_flags(offline, refresh);
+            let cps = cargo::Cargo::select(cm, &sel.into())?;
+            let root = cargo::Cargo::inherited_root(cm, &cps)?;
+            for cp in cps.iter().chain(root.iter()) {
+                cp.update_deps(policy, pre, rust_version.as_ref(), color.clone())
+                    .await?;
+            }
+        }
+        DepiCommand::Outdated {
+            pre,
+            rust_version,
+            offline,
+            refresh,
+            color,
+            sel,
+        } => {
+            let cm = CacheMode::from_flags(offline, refresh);
+            let cps = cargo::Cargo::select(cm, &sel.into())?;
+            let root = cargo::Cargo::inherited_root(cm, &cps)?;
+            let mut outdated = 0;
+            for cp in cps.iter().chain(root.iter()) {
+                outdated += cp
+                    .outdated(pre, rust_version.as_ref(), color.clone())
+                    .await?;
+            }
+            if outdated > 0 {
+                return Err(anyhow!("outdated dependencies: {}", outdated));
+            }
         }
-        DepiCommand::Remove { names, color } => {
-            let cp = cargo::Cargo::from_cur()?;
-            cp.remove_deps(names, color).await?;
+        DepiCommand::List {
+            offline,
+            refresh,
+            color,
+            sel,
+        } => {
+            let cm = CacheMode::from_flags(offline, refresh);
+            for cp in cargo::Cargo::select(cm, &sel.into())? {
+                cp.list(color.clone()).await?;
+            }
         }
-        DepiCommand::Update { color } => {
-            let cp = cargo::Cargo::from_cur()?;
-            cp.update_deps(color).await?;
+        DepiCommand::Search {
+            query,
+            page,
+            registry,
+            add,
+            color,
+        } => {
+            // fail before searching if there is nothing to add to
+            let cp = match add {
+                true => Some(cargo::Cargo::from_cur(CacheMode::default())?),
+                false => None,
+            };
+            let loaded;
+            let regs: &dyn RegistryProvider = match &cp {
+                Some(cp) => cp.registries.as_ref(),
+                None => {
+                    loaded = Registries::load(".", CacheMode::default())?;
+                    &loaded
+                }
+            };
+            let found =
+                cargo::Cargo::search(&query, page, registry.as_ref(), regs, color.clone()).await?;
+
+            if let Some(cp) = cp
+                && !found.is_empty()
+                && let Some(n) = utils::funcs::read_choice("add", found.len())?
+            {
+                cp.append_deps(
+                    &found[n - 1].name,
+                    registry.as_ref(),
+                    false,
+                    None,
+                    false,
+                    color,
+                )
+                .await?;
+            }
         }
-        DepiCommand::List { color } => {
-            let cp = cargo::Cargo::from_cur()?;
-            cp.list(color).await?;
+        DepiCommand::Info {
+            dep,
+            registry,
+            offline,
+            refresh,
+            color,
+        } => {
+            let regs = Registries::load(".", CacheMode::from_flags(offline, refresh))?;
+            cargo::Cargo::info(dep, registry, &regs, color).await?;
         }
+        DepiCommand::Feature { command } => match command {
+            FeatureCommand::Add {
+                dep,
+                features,
+                offline,
+                refresh,
+                color,
+            } => {
+                let cp = cargo::Cargo::from_cur(CacheMode::from_flags(offline, refresh))?;
+                cp.add_features(dep, features, color).await?;
+            }
+            FeatureCommand::Remove {
+                dep,
+                features,
+                color,
+            } => {
+                let cp = cargo::Cargo::from_cur(CacheMode::default())?;
+                cp.remove_features(dep, features, color).await?;
+            }
+            FeatureCommand::List {
+                dep,
+                offline,
+                refresh,
+                color,
+            } => {
+                let cp = cargo::Cargo::from_cur(CacheMode::from_flags(offline, refresh))?;
+                cp.list_features(dep, color).await?;
+            }
+        },
         DepiCommand::Alias { command } => {
             let mut a_s = storage::AliasStorage::load()?;
             match command {
@@ -0,0 +1,453 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use anyhow::{Result, anyhow};
+use log::info;
+use toml::{Table, Value};
+
+pub const CRATES_IO: &str = "crates-io";
+const CRATES_IO_GIT_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+#[derive(Debug, Clone, Default)]
+pub struct RegistryConfig {
+    pub index: Option<String>,
+    pub token: Option<String>,
+}
+
+/// `[source.<name>]` table.
+#[derive(Debug, Clone, Default)]
+pub struct SourceConfig {
+    pub replace_with: Option<String>,
+    pub registry: Option<String>,
+    /// Absolute, relative paths are resolved against the config's parent
+    /// of `.cargo`.
+    pub local_registry: Option<PathBuf>,
+    /// Vendored crates, as absolute as `local_registry`.
+    pub directory: Option<PathBuf>,
+}
+
+/// Where crates of a registry are fetched from after source replacement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    CratesIo,
+    Index { url: String, token: Option<String> },
+    LocalRegistry(PathBuf),
+    Directory(PathBuf),
+}
+
+/// Registry related parts of the cargo configuration, merged the way cargo
+/// does: configs closer to the project win over the ones above and over
+/// `$CARGO_HOME/config.toml`.
+#[derive(Debug, Clone, Default)]
+pub struct CargoConfig {
+    pub registries: HashMap<String, RegistryConfig>,
+    pub sources: HashMap<String, SourceConfig>,
+    /// `[registry] token` from the credentials, used for crates.io.
+    pub crates_io_token: Option<String>,
+}
+
+impl CargoConfig {
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::load_with(dir, cargo_home().ok(), env::vars())
+    }
+    /// `load` with the cargo home and the environment given.
+    fn load_with<P, I>(dir: P, home: Option<PathBuf>, vars: I) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut res = Self::default();
+
+        let mut dir = Some(fs::canonicalize(dir.as_ref())?);
+        while let Some(d) = dir {
+            if home.as_deref() != Some(d.join(".cargo").as_path()) {
+                res.merge_dir(&d.join(".cargo"), &d)?;
+            }
+            dir = d.parent().map(|p| p.to_path_buf());
+        }
+        if let Some(home) = &home {
+            let base = home.parent().unwrap_or(home).to_path_buf();
+            res.merge_dir(home, &base)?;
+            for name in ["credentials.toml", "credentials"] {
+                let path = home.join(name);
+                if path.is_file() {
+                    res.merge_file(&path, &base)?;
+                    break;
+                }
+            }
+        }
+        res.merge_env(vars);
+        Ok(res)
+    }
+    fn merge_dir(&mut self, dot_cargo: &Path, base: &Path) -> Result<()> {
+        for name in ["config.toml", "config"] {
+            let path = dot_cargo.join(name);
+            if path.is_file() {
+                return self.merge_file(&path, base);
+            }
+        }
+        Ok(())
+    }
+    /// Adds the settings not set yet, `base` is the directory relative
+    /// paths in the file start from.
+    fn merge_file(&mut self, path: &Path, base: &Path) -> Result<()> {
+        info!("reading cargo config {}", path.display());
+        let content = fs::read_to_string(path)?;
+        let table = content
+            .parse::<Table>()
+            .map_err(|e| anyhow!("invalid cargo config {}: {}", path.display(), e))?;
+
+        let get = |t: &Table, k: &str| t.get(k).and_then(|v| v.as_str()).map(|v| v.to_string());
+
+        if let Some(Value::Table(registries)) = table.get("registries") {
+            for (name, t) in registries {
+                let Value::Table(t) = t else {
+                    continue;
+                };
+                let rc = self.registries.entry(name.to_string()).or_default();
+                if rc.index.is_none() {
+                    rc.index = get(t, "index");
+                }
+                if rc.token.is_none() {
+                    rc.token = get(t, "token");
+                }
+            }
+        }
+        if let Some(Value::Table(sources)) = table.get("source") {
+            for (name, t) in sources {
+                let Value::Table(t) = t else {
+                    continue;
+                };
+                let sc = self.sources.entry(name.to_string()).or_default();
+                if sc.replace_with.is_none() {
+                    sc.replace_with = get(t, "replace-with");
+                }
+                if sc.registry.is_none() {
+                    sc.registry = get(t, "registry");
+                }
+                if sc.local_registry.is_none() {
+                    sc.local_registry = get(t, "local-registry").map(|p| base.join(p));
+                }
+                if sc.directory.is_none() {
+                    sc.directory = get(t, "directory").map(|p| base.join(p));
+                }
+            }
+        }
+        if let Some(Value::Table(registry)) = table.get("registry")
+            && self.crates_io_token.is_none()
+        {
+            self.crates_io_token = get(registry, "token");
+        }
+        Ok(())
+    }
+    /// `CARGO_REGISTRIES_<NAME>_INDEX`/`_TOKEN` and `CARGO_REGISTRY_TOKEN`
+    /// override the files.
+    fn merge_env<I: IntoIterator<Item = (String, String)>>(&mut self, vars: I) {
+        for (k, v) in vars {
+            if k == "CARGO_REGISTRY_TOKEN" {
+                self.crates_io_token = Some(v);
+                continue;
+            }
+            let Some(rest) = k.strip_prefix("CARGO_REGISTRIES_") else {
+                continue;
+            };
+            let (name, field) = if let Some(name) = rest.strip_suffix("_INDEX") {
+                (name, "index")
+            } else if let Some(name) = rest.strip_suffix("_TOKEN") {
+                (name, "token")
+            } else {
+                continue;
+            };
+            // the env name is upper case with `_` for `-`, find the
+            // configured registry it stands for
+            let name = self
+                .registries
+                .keys()
+                .find(|n| n.to_uppercase().replace('-', "_") == name)
+                .cloned()
+                .unwrap_or(name.to_lowercase().replace('_', "-"));
+            let rc = self.registries.entry(name).or_default();
+            match field {
+                "index" => rc.index = Some(v),
+                _ => rc.token = Some(v),
+            }
+        }
+    }
+
+    pub fn token<S: AsRef<str>>(&self, registry: S) -> Option<String> {
+        match registry.as_ref() {
+            CRATES_IO => self.crates_io_token.clone(),
+            name => self.registries.get(name).and_then(|r| r.token.clone()),
+        }
+    }
+
+    /// Follows `replace-with` from the source of the registry (`crates-io`
+    /// for crates.io) to the one crates are actually fetched from.
+    pub fn source<S: AsRef<str>>(&self, registry: S) -> Result<Source> {
+        let mut cur = registry.as_ref().to_string();
+        let mut seen = HashSet::new();
+        loop {
+            if !seen.insert(cur.clone()) {
+                return Err(anyhow!("source replacement cycle at {}", cur));
+            }
+            if let Some(sc) = self.sources.get(&cur) {
+                if let Some(next) = &sc.replace_with {
+                    info!("source {} is replaced with {}", cur, next);
+                    cur = next.to_string();
+                    continue;
+                }
+                if let Some(url) = &sc.registry {
+                    return Ok(self.index_source(&cur, url));
+                }
+                if let Some(path) = &sc.local_registry {
+                    return Ok(Source::LocalRegistry(path.clone()));
+                }
+                if let Some(path) = &sc.directory {
+                    return Ok(Source::Directory(path.clone()));
+                }
+            }
+            if cur == CRATES_IO {
+                return Ok(Source::CratesIo);
+            }
+            return match self.registries.get(&cur).and_then(|r| r.index.as_ref()) {
+                Some(url) => Ok(self.index_source(&cur, url)),
+                None => Err(anyhow!("registry {} is not configured", cur)),
+            };
+        }
+    }
+    fn index_source(&self, name: &str, url: &str) -> Source {
+        if url.trim_end_matches('/') == CRATES_IO_GIT_INDEX {
+            return Source::CratesIo;
+        }
+        Source::Index {
+            url: url.to_string(),
+            token: self.token(name),
+        }
+    }
+}
+
+/// `$CARGO_HOME`, `~/.cargo` by default.
+pub fn cargo_home() -> Result<PathBuf> {
+    if let Ok(home) = env::var("CARGO_HOME") {
+        return Ok(PathBuf::from(home));
+    }
+    let home = match env::consts::OS {
+        "windows" => env::var("USERPROFILE")?,
+        _ => env::var("HOME")?,
+    };
+    Ok(Path::new(&home).join(".cargo"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    /// Temporary directory for one test, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("depi-config-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(fs::canonicalize(dir).unwrap())
+        }
+        fn write(&self, rel: &str, content: &str) -> PathBuf {
+            let path = self.0.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            path
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn load(dir: &Path, home: &Path, vars: &[(&str, &str)]) -> CargoConfig {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        CargoConfig::load_with(dir, Some(home.to_path_buf()), vars).unwrap()
+    }
+
+    #[test]
+    fn source_replacement() {
+        let t = TempDir::new("sources");
+        t.write(
+            ".cargo/config.toml",
+            r#"
+[source.crates-io]
+replace-with = "mirror"
+
+[source.mirror]
+replace-with = "vendored-sources"
+
+[source.vendored-sources]
+directory = "vendor"
+
+[source.local]
+local-registry = "/srv/registry"
+
+[source.a]
+replace-with = "b"
+
+[source.b]
+replace-with = "a"
+
+[source.old-index]
+registry = "https://github.com/rust-lang/crates.io-index/"
+
+[registries.internal]
+index = "sparse+https://internal.example.com/index/"
+
+[registries.corp]
+index = "sparse+https://corp.example.com/index/"
+
+[source.corp]
+replace-with = "local"
+"#,
+        );
+        // the nearer config wins for the same source, its paths start
+        // next to its own `.cargo`
+        t.write(
+            "proj/.cargo/config",
+            "[source.vendored-sources]\ndirectory = \"third-party\"\n",
+        );
+        let home = t.0.join("home");
+
+        let cfg = load(&t.0, &home, &[]);
+        assert_eq!(
+            cfg.source(CRATES_IO).unwrap(),
+            Source::Directory(t.0.join("vendor"))
+        );
+        let cfg = load(&t.0.join("proj"), &home, &[]);
+        assert_eq!(
+            cfg.source(CRATES_IO).unwrap(),
+            Source::Directory(t.0.join("proj/third-party"))
+        );
+        assert_eq!(
+            cfg.source("corp").unwrap(),
+            Source::LocalRegistry(PathBuf::from("/srv/registry"))
+        );
+        assert_eq!(
+            cfg.source("internal").unwrap(),
+            Source::Index {
+                url: "sparse+https://internal.example.com/index/".to_string(),
+                token: None
+            }
+        );
+        assert_eq!(cfg.source("old-index").unwrap(), Source::CratesIo);
+
+        let err = cfg.source("a").unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+        let err = cfg.source("nowhere").unwrap_err();
+        assert!(err.to_string().contains("not configured"), "{}", err);
+
+        // nothing configured
+        let t = TempDir::new("no-sources");
+        let cfg = load(&t.0, &t.0.join("home"), &[]);
+        assert_eq!(cfg.source(CRATES_IO).unwrap(), Source::CratesIo);
+    }
+
+    #[test]
+    fn nearest_config_wins() {
+        let t = TempDir::new("nearest");
+        let home = t.0.join("home");
+        t.write(
+            "home/config.toml",
+            "[registries.internal]\nindex = \"sparse+https://home.example.com/\"\n\n[registries.other]\nindex = \"sparse+https://other.example.com/\"\n",
+        );
+        t.write(
+            ".cargo/config.toml",
+            "[registries.internal]\nindex = \"sparse+https://outer.example.com/\"\n",
+        );
+        t.write(
+            "proj/.cargo/config.toml",
+            "[registries.internal]\nindex = \"sparse+https://inner.example.com/\"\n",
+        );
+        let index = |cfg: &CargoConfig, name: &str| cfg.registries[name].index.clone().unwrap();
+
+        let cfg = load(&t.0.join("proj"), &home, &[]);
+        assert_eq!(index(&cfg, "internal"), "sparse+https://inner.example.com/");
+        assert_eq!(index(&cfg, "other"), "sparse+https://other.example.com/");
+        let cfg = load(&t.0, &home, &[]);
+        assert_eq!(index(&cfg, "internal"), "sparse+https://outer.example.com/");
+
+        // `.cargo/config.toml` goes before the old `.cargo/config`
+        t.write(
+            "proj/.cargo/config",
+            "[registries.internal]\nindex = \"sparse+https://legacy.example.com/\"\n",
+        );
+        let cfg = load(&t.0.join("proj"), &home, &[]);
+        assert_eq!(index(&cfg, "internal"), "sparse+https://inner.example.com/");
+    }
+
+    #[test]
+    fn env_overrides_files() {
+        let t = TempDir::new("env");
+        let home = t.0.join("home");
+        t.write(
+            ".cargo/config.toml",
+            "[registries.my-reg]\nindex = \"sparse+https://file.example.com/\"\ntoken = \"file\"\n",
+        );
+        let cfg = load(
+            &t.0,
+            &home,
+            &[
+                (
+                    "CARGO_REGISTRIES_MY_REG_INDEX",
+                    "sparse+https://env.example.com/",
+                ),
+                ("CARGO_REGISTRIES_NEW_ONE_TOKEN", "new"),
+                ("CARGO_REGISTRY_TOKEN", "crates"),
+                ("CARGO_REGISTRIES_MY_REG", "ignored"),
+            ],
+        );
+        let rc = &cfg.registries["my-reg"];
+        assert_eq!(rc.index.as_deref(), Some("sparse+https://env.example.com/"));
+        assert_eq!(cfg.token("my-reg").as_deref(), Some("file"));
+        assert_eq!(cfg.token("new-one").as_deref(), Some("new"));
+        assert_eq!(cfg.token(CRATES_IO).as_deref(), Some("crates"));
+        assert_eq!(cfg.registries.len(), 2);
+    }
+
+    #[test]
+    fn credentials() {
+        let t = TempDir::new("credentials");
+        let home = t.0.join("home");
+        t.write(
+            "home/config.toml",
+            "[registries.internal]\nindex = \"sparse+https://internal.example.com/\"\n\n[registries.pinned]\nindex = \"sparse+https://pinned.example.com/\"\ntoken = \"from-config\"\n",
+        );
+        t.write(
+            "home/credentials",
+            "[registry]\ntoken = \"old-crates\"\n\n[registries.internal]\ntoken = \"old\"\n",
+        );
+        let cfg = load(&t.0, &home, &[]);
+        assert_eq!(cfg.token("internal").as_deref(), Some("old"));
+        assert_eq!(cfg.token(CRATES_IO).as_deref(), Some("old-crates"));
+
+        // `credentials.toml` replaces the old file, config tokens stay
+        t.write(
+            "home/credentials.toml",
+            "[registries.internal]\ntoken = \"new\"\n\n[registries.pinned]\ntoken = \"from-credentials\"\n",
+        );
+        let cfg = load(&t.0, &home, &[]);
+        assert_eq!(cfg.token("internal").as_deref(), Some("new"));
+        assert_eq!(cfg.token(CRATES_IO), None);
+        assert_eq!(cfg.token("pinned").as_deref(), Some("from-config"));
+
+        let cfg = load(&t.0, &home, &[("CARGO_REGISTRIES_INTERNAL_TOKEN", "env")]);
+        assert_eq!(cfg.token("internal").as_deref(), Some("env"));
+        assert_eq!(
+            cfg.source("internal").unwrap(),
+            Source::Index {
+                url: "sparse+https://internal.example.com/".to_string(),
+                token: Some("env".to_string())
+            }
+        );
+    }
+}
@@ -1,143 +1,445 @@
+use std::fmt;
+
 use anyhow::{Result, anyhow};
-use toml::{Table, Value as TValue, value::Array};
+use log::info;
+use toml_edit::{Array, InlineTable, Item, TableLike, Value};
+
+use crate::utils::ver::{Version, VersionReq};
+
+/// Kind of dependency table, also used inside `[target.<platform>]`.
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DKind {
+    Normal,
+    Dev,
+    Build,
+}
+
+impl DKind {
+    pub const ALL: [DKind; 3] = [DKind::Normal, DKind::Dev, DKind::Build];
+
+    pub fn to_cargo_field(self) -> &'static str {
+        match self {
+            DKind::Normal => "dependencies",
+            DKind::Dev => "dev-dependencies",
+            DKind::Build => "build-dependencies",
+        }
+    }
+}
 
 #[derive(Hash, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DType {
     Normal,
     Dev,
     Build,
-    OS(String),
+    /// `[target.<platform>.<kind>]`, platform is either a `cfg(...)`
+    /// expression or a target triple.
+    Target(String, DKind),
+    /// `[workspace.dependencies]` of a workspace root, inherited by members
+    /// with `{ workspace = true }`.
+    Workspace,
 }
 
 impl<S: AsRef<str>> From<S> for DType {
+    /// `dev`, `build`, `normal` or a platform as normalized by
+    /// `cfg::Platform`.
     fn from(s: S) -> Self {
-        let s = s.as_ref().to_lowercase();
-        match s.as_str() {
+        let s = s.as_ref().trim();
+        match s.to_lowercase().as_str() {
             "dev" => Self::Dev,
             "build" => Self::Build,
             "normal" => Self::Normal,
-            ss if ss.trim().len() == 0 => Self::Normal,
-            os => Self::OS(os.to_string()),
+            "" => Self::Normal,
+            _ => Self::Target(s.to_string(), DKind::Normal),
+        }
+    }
+}
+
+impl From<DKind> for DType {
+    fn from(kind: DKind) -> Self {
+        match kind {
+            DKind::Normal => Self::Normal,
+            DKind::Dev => Self::Dev,
+            DKind::Build => Self::Build,
         }
     }
 }
 
 impl DType {
+    pub fn kind(&self) -> DKind {
+        match self {
+            DType::Normal | DType::Workspace => DKind::Normal,
+            DType::Dev => DKind::Dev,
+            DType::Build => DKind::Build,
+            DType::Target(_, kind) => *kind,
+        }
+    }
     pub fn to_cargo_field(&self) -> String {
         match self {
-            DType::Normal => "dependencies".to_string(),
-            DType::Dev => "dev-dependencies".to_string(),
-            DType::Build => "build-dependencies".to_string(),
-            DType::OS(os) => format!("target.'cfg({})'.dependencies", os),
+            DType::Target(platform, kind) => {
+                format!("target.'{}'.{}", platform, kind.to_cargo_field())
+            }
+            DType::Workspace => "workspace.dependencies".to_string(),
+            dtype => dtype.kind().to_cargo_field().to_string(),
         }
     }
 }
 
-impl ToString for DType {
-    fn to_string(&self) -> String {
-        (match self {
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
             DType::Normal => "normal",
             DType::Dev => "dev",
             DType::Build => "build",
-            DType::OS(os) => os,
-        })
-        .to_string()
+            DType::Target(platform, _) => platform,
+            DType::Workspace => "workspace",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How far `depi update` may move a requirement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpdatePolicy {
+    /// Newest version still matched by the current requirement.
+    Compatible,
+    /// Newest version with the same major.
+    Minor,
+    /// Newest version with the same major and minor.
+    Patch,
+    /// Newest version, requirements which cannot be moved are kept.
+    #[default]
+    Latest,
+    /// Newest version whatever the current requirement, `--latest`:
+    /// requirements which cannot be moved are replaced.
+    Replace,
+}
+
+impl UpdatePolicy {
+    pub fn from_flags(compatible: bool, minor: bool, patch: bool, latest: bool) -> Self {
+        if latest {
+            Self::Replace
+        } else if compatible {
+            Self::Compatible
+        } else if minor {
+            Self::Minor
+        } else if patch {
+            Self::Patch
+        } else {
+            Self::Latest
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitRev {
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+/// Where the dependency comes from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DSource {
+    #[default]
+    CratesIo,
+    Registry(String),
+    Path(String),
+    Git {
+        url: String,
+        rev: Option<GitRev>,
+    },
+    Workspace,
+}
+
+impl DSource {
+    fn from_toml(body: &dyn TableLike) -> Option<Self> {
+        let get = |k: &str| body.get(k).and_then(|v| v.as_str()).map(|v| v.to_string());
+
+        if body.get("workspace").and_then(|v| v.as_bool()) == Some(true) {
+            Some(Self::Workspace)
+        } else if let Some(path) = get("path") {
+            Some(Self::Path(path))
+        } else if let Some(url) = get("git") {
+            let rev = if let Some(b) = get("branch") {
+                Some(GitRev::Branch(b))
+            } else if let Some(t) = get("tag") {
+                Some(GitRev::Tag(t))
+            } else {
+                get("rev").map(GitRev::Rev)
+            };
+            Some(Self::Git { url, rev })
+        } else if let Some(registry) = get("registry") {
+            Some(Self::Registry(registry))
+        } else if body.contains_key("version") {
+            Some(Self::CratesIo)
+        } else {
+            None
+        }
+    }
+    /// Keys of the dependency table that describe this source.
+    fn keys(&self) -> &'static [&'static str] {
+        match self {
+            DSource::CratesIo => &[],
+            DSource::Registry(_) => &["registry"],
+            DSource::Path(_) => &["path"],
+            DSource::Git { rev, .. } => match rev {
+                Some(GitRev::Branch(_)) => &["git", "branch"],
+                Some(GitRev::Tag(_)) => &["git", "tag"],
+                Some(GitRev::Rev(_)) => &["git", "rev"],
+                None => &["git"],
+            },
+            DSource::Workspace => &["workspace"],
+        }
+    }
+    fn to_toml(&self, body: &mut InlineTable) {
+        match self {
+            DSource::CratesIo => {}
+            DSource::Registry(registry) => {
+                body.insert("registry", Value::from(registry.as_str()));
+            }
+            DSource::Path(path) => {
+                body.insert("path", Value::from(path.as_str()));
+            }
+            DSource::Git { url, rev } => {
+                body.insert("git", Value::from(url.as_str()));
+                match rev {
+                    Some(GitRev::Branch(b)) => body.insert("branch", Value::from(b.as_str())),
+                    Some(GitRev::Tag(t)) => body.insert("tag", Value::from(t.as_str())),
+                    Some(GitRev::Rev(r)) => body.insert("rev", Value::from(r.as_str())),
+                    None => None,
+                };
+            }
+            DSource::Workspace => {
+                body.insert("workspace", Value::from(true));
+            }
+        }
+    }
+}
+
+impl fmt::Display for DSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DSource::CratesIo => write!(f, "crates.io"),
+            DSource::Registry(registry) => write!(f, "registry:{}", registry),
+            DSource::Path(path) => write!(f, "path:{}", path),
+            DSource::Git { url, rev } => match rev {
+                Some(GitRev::Branch(b)) => write!(f, "git:{}#branch={}", url, b),
+                Some(GitRev::Tag(t)) => write!(f, "git:{}#tag={}", url, t),
+                Some(GitRev::Rev(r)) => write!(f, "git:{}#rev={}", url, r),
+                None => write!(f, "git:{}", url),
+            },
+            DSource::Workspace => write!(f, "workspace"),
+        }
+    }
+}
+
+/// Dependency keys with their own `Dep` fields, everything else goes to
+/// `Dep::extra`.
+const KNOWN_KEYS: [&str; 6] = [
+    "version",
+    "features",
+    "optional",
+    "default-features",
+    "default_features",
+    "package",
+];
+
+#[derive(Debug, Clone, Default)]
 pub struct Dep {
     pub name: String,
+    /// Version requirement, empty when the source does not pin one
+    /// (e.g. plain path, git or workspace dependencies).
     pub version: String,
     pub features: Option<Vec<String>>,
+    pub source: DSource,
+    pub optional: Option<bool>,
+    pub default_features: Option<bool>,
+    pub package: Option<String>,
+    /// Remaining keys (`public`, `artifact`, `registry-index`, ...) in the
+    /// order they were read.
+    pub extra: Vec<(String, Value)>,
 }
 
 impl Dep {
-    pub fn from_toml<S: AsRef<str>>(name: S, attrs: TValue) -> Result<Self> {
+    pub fn from_toml<S: AsRef<str>>(name: S, attrs: &Item) -> Result<Self> {
         let name = name.as_ref();
-        match attrs {
-            TValue::String(version) => Ok(Self {
+        if let Some(version) = attrs.as_str() {
+            return Ok(Self {
                 name: name.to_string(),
                 version: version.to_string(),
-                features: None,
-            }),
-            TValue::Table(body) => {
-                let version = if let Some(TValue::String(version)) = body.get("version") {
-                    version.to_string()
-                } else {
-                    return Err(anyhow!("parse error: version"));
-                };
+                ..Default::default()
+            });
+        }
+        match attrs.as_table_like() {
+            Some(body) => {
+                let source = DSource::from_toml(body).ok_or(anyhow!(
+                    "parse error: {} has no version, path, git or workspace",
+                    name
+                ))?;
+                let version = body
+                    .get("version")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
 
-                let mut fs = Vec::new();
-                if let Some(TValue::Array(afs)) = body.get("features") {
-                    for f in afs {
-                        if let TValue::String(f) = f {
-                            fs.push(f.to_string())
-                        }
+                let features = body.get("features").and_then(|f| f.as_array()).map(|afs| {
+                    afs.iter()
+                        .filter_map(|f| f.as_str())
+                        .map(|f| f.to_string())
+                        .collect::<Vec<_>>()
+                });
+                let optional = body.get("optional").and_then(|v| v.as_bool());
+                // `default_features` is the old spelling cargo still accepts
+                let default_features = body
+                    .get("default-features")
+                    .or(body.get("default_features"))
+                    .and_then(|v| v.as_bool());
+                let package = body
+                    .get("package")
+                    .and_then(|v| v.as_str())
+                    .map(|v| v.to_string());
+
+                let mut extra = Vec::new();
+                for (k, v) in body.iter() {
+                    if KNOWN_KEYS.contains(&k) || source.keys().contains(&k) {
+                        continue;
+                    }
+                    match v.as_value() {
+                        Some(v) => extra.push((k.to_string(), v.clone())),
+                        None => return Err(anyhow!("parse error: {}.{} is not a value", name, k)),
                     }
-                } else {
-                    return Err(anyhow!("parse error: features"));
                 }
 
                 Ok(Self {
                     name: name.to_string(),
                     version,
-                    features: Some(fs),
+                    features,
+                    source,
+                    optional,
+                    default_features,
+                    package,
+                    extra,
                 })
             }
-            _ => Err(anyhow!("parse error: incorrect attrs type")),
+            None => Err(anyhow!("parse error: incorrect attrs type")),
         }
     }
-    pub fn to_toml(&self) -> (String, TValue) {
-        if let Some(fs) = &self.features {
-            let mut body = Table::new();
+    pub fn to_toml(&self) -> (String, Item) {
+        if self.features.is_none()
+            && self.source == DSource::CratesIo
+            && self.optional.is_none()
+            && self.default_features.is_none()
+            && self.package.is_none()
+            && self.extra.is_empty()
+        {
+            return (
+                self.name.to_string(),
+                toml_edit::value(self.version.as_str()),
+            );
+        }
 
+        let mut body = InlineTable::new();
+        if !self.version.is_empty() {
+            body.insert("version", Value::from(self.version.as_str()));
+        }
+        self.source.to_toml(&mut body);
+        if let Some(package) = &self.package {
+            body.insert("package", Value::from(package.as_str()));
+        }
+        if let Some(fs) = &self.features {
             let mut afs = Array::new();
             for f in fs {
-                afs.push(TValue::String(f.to_string()))
+                afs.push(f.as_str())
             }
+            body.insert("features", Value::Array(afs));
+        }
+        if let Some(df) = self.default_features {
+            body.insert("default-features", Value::from(df));
+        }
+        if let Some(optional) = self.optional {
+            body.insert("optional", Value::from(optional));
+        }
+        for (k, v) in &self.extra {
+            body.insert(k, v.clone());
+        }
+        body.fmt();
 
-            body.insert(
-                "version".to_string(),
-                TValue::String(self.version.to_string()),
-            );
-            body.insert("features".to_string(), TValue::Array(afs));
-
-            (self.name.to_string(), TValue::Table(body))
-        } else {
-            (
-                self.name.to_string(),
-                TValue::String(self.version.to_string()),
-            )
+        (self.name.to_string(), Item::Value(Value::InlineTable(body)))
+    }
+    /// Only registry dependencies with a version can be bumped.
+    pub fn is_updatable(&self) -> bool {
+        matches!(self.source, DSource::CratesIo | DSource::Registry(_)) && !self.version.is_empty()
+    }
+    /// Published name of the crate, `package` for renamed dependencies.
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+    /// Name of the alternate registry, `None` for crates.io.
+    pub fn registry(&self) -> Option<&str> {
+        match &self.source {
+            DSource::Registry(r) => Some(r),
+            _ => None,
+        }
+    }
+    /// Version requirement or, if there is none, the source of the dependency.
+    pub fn version_label(&self) -> String {
+        match (&self.source, self.version.is_empty()) {
+            (DSource::CratesIo, _) => self.version.to_string(),
+            (s, true) => s.to_string(),
+            (s, false) => format!("{} ({})", self.version, s),
         }
     }
-    pub async fn update_version(self) -> Result<Self> {
-        let fd = api::fetch_crates_dep(&self.name).await?;
+    /// Moves the requirement to the newest version allowed by the policy
+    /// keeping its operator, requirements like `>=1, <2` or `*` are left as
+    /// is unless the policy is `Replace`.
+    pub fn update_version(
+        self,
+        fd: &api::CratesDep,
+        policy: UpdatePolicy,
+        pre: bool,
+        msrv: Option<&Version>,
+    ) -> Result<Self> {
+        let req = VersionReq::parse(&self.version)?;
+        let Some(last) = fd.get_update_version(&req, policy, pre, msrv) else {
+            info!("no {:?} update for {} {}", policy, self.name, self.version);
+            return Ok(self);
+        };
+        let last = Version::parse(last)?;
         let mut d = self;
-        d.version = fd.get_last_version();
+        match (req.bump(&last), policy) {
+            (Some(req), _) => d.version = req.to_string(),
+            (None, UpdatePolicy::Replace) => d.version = last.to_string(),
+            (None, _) => info!("keep {} requirement {}", d.name, d.version),
+        }
         Ok(d)
     }
 }
 
-pub fn normalize(pdep: &parse::PDep, fdep: &api::CratesDep) -> Result<Dep> {
+pub fn normalize(
+    pdep: &parse::PDep,
+    fdep: &api::CratesDep,
+    pre: bool,
+    msrv: Option<&Version>,
+) -> Result<Dep> {
     let name = fdep.name.to_string();
-    let version = if pdep.version.is_empty() {
-        fdep.get_last_version()
-    } else if fdep.has_version(&pdep.version) {
-        pdep.version.to_string()
+    let (version, resolved) = if pdep.version.is_empty() {
+        let last = fdep.get_last_version(pre, msrv)?;
+        (last.clone(), last)
     } else {
-        return Err(anyhow!("invalid version"));
+        let req = VersionReq::parse(&pdep.version)
+            .map_err(|e| anyhow!("invalid version {}: {}", pdep.version, e))?;
+        (req.to_string(), fdep.get_last_matching_version(&req, msrv)?)
     };
     let features = if pdep.features.is_empty() {
         None
-    } else if let Some(ffeatures) = fdep.get_features(&version) {
+    } else if let Some(ffeatures) = fdep.get_features(&resolved) {
         let mut rfeatures = Vec::new();
 
         let pfeatures = pdep.features.split(',').collect::<Vec<_>>();
         let mut valid_features = true;
         let mut invalid_feat = String::new();
         for pfeat in pfeatures {
-            if !ffeatures.contains(&pfeat.to_owned()) {
+            if !ffeatures.contains_key(pfeat) {
                 valid_features = false;
                 invalid_feat = pfeat.to_string();
                 break;
@@ -157,82 +459,212 @@ pub fn normalize(pdep: &parse::PDep, fdep: &api::CratesDep) -> Result<Dep> {
         name,
         version,
         features,
+        ..Default::default()
     })
 }
 
 pub mod api {
-    use crate::utils;
+    use super::UpdatePolicy;
+    use crate::utils::ver::{Version, VersionReq};
 
-    use anyhow::Result;
-    use serde_json::Value as JValue;
-    use std::collections::HashMap;
+    use anyhow::{Result, anyhow};
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
 
     #[derive(Debug, Clone)]
     pub struct CratesDep {
         pub name: String,
-        pub versions: HashMap<String, Vec<String>>,
+        pub versions: HashMap<String, CratesVersion>,
     }
 
-    impl CratesDep {
-        pub fn get_last_version(&self) -> String {
-            self.versions
-                .iter()
-                .filter_map(|(v, _)| utils::ver::OrdVersion::parse(v).ok())
-                .max()
-                .unwrap()
-                .to_string()
-        }
-        pub fn has_version(&self, vs: &str) -> bool {
-            self.versions.contains_key(vs)
-        }
-        pub fn get_features(&self, vs: &str) -> Option<&[String]> {
-            self.versions.get(vs).map(|fs| fs.as_slice())
-        }
-    }
-
-    pub async fn fetch_crates_dep<S: AsRef<str>>(name: S) -> Result<CratesDep> {
-        let mut vhm = HashMap::new();
-
-        let url = format!("https://crates.io/api/v1/crates/{}", name.as_ref());
-        let cli = reqwest::Client::new();
-        let body = cli
-            .get(&url)
-            .header("User-Agent", "depi/0.1.0")
-            .send()
-            .await?
-            .text()
-            .await?;
-
-        let obj = serde_json::from_str::<JValue>(&body)?;
-
-        if let JValue::Object(obj) = obj {
-            if let Some(JValue::Array(arr)) = obj.get("versions") {
-                for v in arr {
-                    if let JValue::Object(vo) = v {
-                        let num = if let Some(JValue::String(num)) = vo.get("num") {
-                            num.to_string()
-                        } else {
-                            continue;
-                        };
-                        let mut features = Vec::new();
-                        if let Some(JValue::Object(fo)) = vo.get("features") {
-                            for (f, _) in fo {
-                                features.push(f.to_string())
-                            }
-                        } else {
-                            continue;
-                        }
+    #[derive(Debug, Clone, Default)]
+    pub struct CratesVersion {
+        /// Feature names with what each one enables directly.
+        pub features: BTreeMap<String, Vec<String>>,
+        pub yanked: bool,
+        pub rust_version: Option<String>,
+    }
 
-                        vhm.insert(num, features);
+    impl CratesVersion {
+        /// Whether the version builds with the `msrv` compiler, versions
+        /// without `rust-version` are assumed to.
+        pub fn supports(&self, msrv: Option<&Version>) -> bool {
+            match (msrv, &self.rust_version) {
+                (Some(msrv), Some(rv)) => {
+                    Version::parse_rust_version(rv).is_ok_and(|rv| rv <= *msrv)
+                }
+                _ => true,
+            }
+        }
+        /// The features and everything they turn on, directly or through
+        /// other features: feature names, `dep:name` and `name/feature`
+        /// entries.
+        pub fn feature_closure<S: AsRef<str>>(&self, roots: &[S]) -> BTreeSet<String> {
+            let mut res = BTreeSet::new();
+            let mut stack = roots
+                .iter()
+                .map(|r| r.as_ref().to_string())
+                .collect::<Vec<_>>();
+            while let Some(f) = stack.pop() {
+                if !res.insert(f.clone()) {
+                    continue;
+                }
+                let Some(en) = self.features.get(&f) else {
+                    continue;
+                };
+                for e in en {
+                    // `name/feature` also turns on the implicit feature of an
+                    // optional dependency, `name?/feature` does not
+                    if let Some((dep, _)) = e.split_once('/')
+                        && self.features.contains_key(dep)
+                    {
+                        stack.push(dep.to_string());
                     }
+                    stack.push(e.to_string());
+                }
+            }
+            res
+        }
+        /// Features of `enabled` another one of them already turns on, with
+        /// the one that does.
+        pub fn redundant_features<S: AsRef<str>>(&self, enabled: &[S]) -> Vec<(String, String)> {
+            let mut res = Vec::new();
+            for f in enabled.iter().map(|f| f.as_ref()) {
+                let by = enabled
+                    .iter()
+                    .map(|g| g.as_ref())
+                    .find(|g| *g != f && self.feature_closure(&[g]).contains(f));
+                if let Some(by) = by {
+                    res.push((f.to_string(), by.to_string()));
                 }
             }
+            res
         }
+        /// Features `default` turns on which `enabled` does not, i.e. what
+        /// `default-features = false` drops.
+        pub fn dropped_defaults<S: AsRef<str>>(&self, enabled: &[S]) -> Vec<String> {
+            let kept = self.feature_closure(enabled);
+            self.feature_closure(&["default"])
+                .into_iter()
+                .filter(|f| f != "default" && self.features.contains_key(f) && !kept.contains(f))
+                .collect()
+        }
+    }
 
-        Ok(CratesDep {
-            name: name.as_ref().to_string(),
-            versions: vhm,
-        })
+    impl CratesDep {
+        /// Newest published version, pre-releases are only considered if `pre`
+        /// is set.
+        pub fn get_last_version(&self, pre: bool, msrv: Option<&Version>) -> Result<String> {
+            self.last_version_by(msrv, |v| pre || !v.is_prerelease())
+                .ok_or(anyhow!(
+                    "no {}{}version of {} found",
+                    if pre { "" } else { "stable " },
+                    if self.versions.values().any(|cv| cv.yanked) {
+                        "unyanked "
+                    } else {
+                        ""
+                    },
+                    self.name
+                ))
+        }
+        /// Newest published version matching the requirement.
+        pub fn get_last_matching_version(
+            &self,
+            req: &VersionReq,
+            msrv: Option<&Version>,
+        ) -> Result<String> {
+            if let Some(v) = self.last_version_by(msrv, |v| req.matches(v)) {
+                return Ok(v);
+            }
+            if let Some(msrv) = msrv
+                && let Some(v) = self.last_version_by(None, |v| req.matches(v))
+            {
+                return Err(anyhow!(
+                    "{} {} matches {} but needs rust {}, newer than {}",
+                    self.name,
+                    v,
+                    req,
+                    self.rust_version(&v).unwrap_or_default(),
+                    msrv
+                ));
+            }
+            let yanked = self
+                .versions
+                .iter()
+                .any(|(vs, cv)| cv.yanked && Version::parse(vs).is_ok_and(|v| req.matches(&v)));
+            Err(anyhow!(
+                "no {}version of {} matches {}",
+                if yanked { "unyanked " } else { "" },
+                self.name,
+                req
+            ))
+        }
+        /// The `rust-version` the version was published with, if any.
+        pub fn rust_version(&self, vs: &str) -> Option<&str> {
+            self.versions.get(vs)?.rust_version.as_deref()
+        }
+        /// Whether the version the requirement pins (its lower bound) was
+        /// yanked.
+        pub fn is_yanked(&self, req: &VersionReq) -> bool {
+            let Some(lb) = req.lower_bound() else {
+                return false;
+            };
+            self.versions
+                .iter()
+                .any(|(vs, cv)| cv.yanked && Version::parse(vs).is_ok_and(|v| v == lb))
+        }
+        /// Newest version the requirement may move to under the policy.
+        pub fn get_update_version(
+            &self,
+            req: &VersionReq,
+            policy: UpdatePolicy,
+            pre: bool,
+            msrv: Option<&Version>,
+        ) -> Option<String> {
+            let stable = |v: &Version| pre || !v.is_prerelease();
+            match (policy, req.lower_bound()) {
+                (UpdatePolicy::Latest | UpdatePolicy::Replace, _) => {
+                    self.last_version_by(msrv, stable)
+                }
+                (UpdatePolicy::Compatible, _) => {
+                    self.last_version_by(msrv, |v| req.matches(v) && stable(v))
+                }
+                (UpdatePolicy::Minor, Some(lb)) => {
+                    self.last_version_by(msrv, |v| v.major == lb.major && stable(v))
+                }
+                (UpdatePolicy::Patch, Some(lb)) => self.last_version_by(msrv, |v| {
+                    v.major == lb.major && v.minor == lb.minor && stable(v)
+                }),
+                (_, None) => None,
+            }
+        }
+        /// Yanked versions and the ones needing a newer rust than `msrv` are
+        /// never picked.
+        fn last_version_by<F: Fn(&Version) -> bool>(
+            &self,
+            msrv: Option<&Version>,
+            f: F,
+        ) -> Option<String> {
+            self.versions
+                .iter()
+                .filter(|(_, cv)| !cv.yanked && cv.supports(msrv))
+                .filter_map(|(vs, _)| Version::parse(vs).ok().map(|v| (v, vs)))
+                .filter(|(v, _)| f(v))
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, vs)| vs.to_string())
+        }
+        pub fn get_features(&self, vs: &str) -> Option<&BTreeMap<String, Vec<String>>> {
+            self.versions.get(vs).map(|cv| &cv.features)
+        }
+        /// Published versions, newest first.
+        pub fn sorted_versions(&self) -> Vec<(&str, &CratesVersion)> {
+            let mut res = self
+                .versions
+                .iter()
+                .filter_map(|(vs, cv)| Version::parse(vs).ok().map(|v| (v, vs.as_str(), cv)))
+                .collect::<Vec<_>>();
+            res.sort_by(|(a, _, _), (b, _, _)| b.cmp(a));
+            res.into_iter().map(|(_, vs, cv)| (vs, cv)).collect()
+        }
     }
 }
 
@@ -263,6 +695,26 @@ pub mod parse {
         res.into_iter().collect()
     }
 
+    /// Names registries can hold: ASCII letters, digits, `-` and `_`,
+    /// starting with a letter.
+    pub fn check_crate_name<S: AsRef<str>>(name: S) -> Result<()> {
+        let name = name.as_ref();
+        if name.is_empty() {
+            return Err(anyhow!("missing crate name"));
+        }
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic())
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "invalid crate name {:?}, only ASCII letters, digits, - and _ are allowed and it must start with a letter",
+                name
+            ));
+        }
+        Ok(())
+    }
+
     pub fn parse_dep<S: AsRef<str>>(s: S) -> Result<PDep> {
         enum DPState {
             Name,
@@ -292,7 +744,8 @@ pub mod parse {
             match c {
                 '@' if matches!(state, DPState::Name)
                     && chars.peek().is_some()
-                    && chars.peek().unwrap().is_alphanumeric()
+                    && (chars.peek().unwrap().is_alphanumeric()
+                        || "^~=<>*".contains(*chars.peek().unwrap()))
                     && !once_version =>
                 {
                     once_version = true;
@@ -308,14 +761,15 @@ pub mod parse {
                 }
                 '!' if !matches!(state, DPState::Target)
                     && chars.peek().is_some()
-                    && chars.peek().unwrap().is_alphanumeric()
+                    && (chars.peek().unwrap().is_alphanumeric()
+                        || "'\"".contains(*chars.peek().unwrap()))
                     && !once_target =>
                 {
                     once_target = true;
                     state = DPState::Target;
                 }
 
-                c if (c.is_alphanumeric() || c == '.' || c == '-' || c == '_')
+                c if (c.is_alphanumeric() || ".-_+^~=<>*, ".contains(c))
                     && matches!(state, DPState::Version) =>
                 {
                     version.push(c)
@@ -330,11 +784,9 @@ pub mod parse {
                 {
                     name.push(c)
                 }
-                c if (c.is_alphanumeric() || c == '-' || c == '_')
-                    && matches!(state, DPState::Target) =>
-                {
-                    target.push(c)
-                }
+                // target is always the last part and may be a whole cfg()
+                // expression, so take everything
+                c if matches!(state, DPState::Target) => target.push(c),
                 c if c.is_alphanumeric() => match state {
                     DPState::Name => name.push(c),
                     DPState::Version => version.push(c),
@@ -358,6 +810,12 @@ pub mod parse {
                 }
             }
         }
+        check_crate_name(&name)?;
+        let target = match target.to_lowercase().as_str() {
+            "" | "normal" | "dev" | "build" => target,
+            _ => super::cfg::Platform::parse_dsl(&target)?.to_string(),
+        };
+
         Ok(PDep {
             name,
             version,
@@ -366,3 +824,432 @@ pub mod parse {
         })
     }
 }
+
+pub mod cfg {
+    use std::{fmt, iter::Peekable, str::Chars};
+
+    use anyhow::{Result, anyhow};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Cfg {
+        /// `unix`, `test`, ...
+        Name(String),
+        /// `target_os = "linux"`, `feature = "std"`, ...
+        KeyPair(String, String),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum CfgExpr {
+        Not(Box<CfgExpr>),
+        All(Vec<CfgExpr>),
+        Any(Vec<CfgExpr>),
+        Value(Cfg),
+    }
+
+    /// Key of a `[target.<platform>]` table.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Platform {
+        /// Target triple, e.g. `x86_64-pc-windows-msvc`.
+        Name(String),
+        Cfg(CfgExpr),
+    }
+
+    impl Platform {
+        pub fn parse<S: AsRef<str>>(s: S) -> Result<Self> {
+            let s = s.as_ref().trim();
+            if let Some(inner) = s.strip_prefix("cfg(").and_then(|s| s.strip_suffix(')')) {
+                return Ok(Self::Cfg(CfgExpr::parse(inner)?));
+            }
+            if s.is_empty()
+                || !s
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            {
+                return Err(anyhow!("invalid target {:?}", s));
+            }
+            Ok(Self::Name(s.to_string()))
+        }
+        /// Like `parse`, but a single identifier (`unix`, `windows`) is taken
+        /// as `cfg(<identifier>)` as the `!target` suffix always did.
+        pub fn parse_dsl<S: AsRef<str>>(s: S) -> Result<Self> {
+            let s = s.as_ref().trim();
+            let s = ['"', '\'']
+                .into_iter()
+                .find_map(|q| s.strip_prefix(q).and_then(|s| s.strip_suffix(q)))
+                .unwrap_or(s);
+            if is_ident(s) {
+                return Ok(Self::Cfg(CfgExpr::Value(Cfg::Name(s.to_string()))));
+            }
+            Self::parse(s)
+        }
+    }
+
+    impl fmt::Display for Platform {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Platform::Name(name) => write!(f, "{}", name),
+                Platform::Cfg(expr) => write!(f, "cfg({})", expr),
+            }
+        }
+    }
+
+    impl CfgExpr {
+        pub fn parse<S: AsRef<str>>(s: S) -> Result<Self> {
+            let mut chars = s.as_ref().chars().peekable();
+            let expr = parse_expr(&mut chars)?;
+            skip_ws(&mut chars);
+            if let Some(c) = chars.next() {
+                return Err(anyhow!("unexpected {:?} in cfg expression", c));
+            }
+            Ok(expr)
+        }
+    }
+
+    impl fmt::Display for CfgExpr {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let join = |es: &[CfgExpr]| {
+                es.iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            match self {
+                CfgExpr::Not(e) => write!(f, "not({})", e),
+                CfgExpr::All(es) => write!(f, "all({})", join(es)),
+                CfgExpr::Any(es) => write!(f, "any({})", join(es)),
+                CfgExpr::Value(Cfg::Name(n)) => write!(f, "{}", n),
+                CfgExpr::Value(Cfg::KeyPair(k, v)) => write!(f, "{} = \"{}\"", k, v),
+            }
+        }
+    }
+
+    fn is_ident(s: &str) -> bool {
+        let mut cs = s.chars();
+        cs.next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && cs.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    fn skip_ws(chars: &mut Peekable<Chars>) {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn parse_ident(chars: &mut Peekable<Chars>) -> Result<String> {
+        skip_ws(chars);
+        let mut ident = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            ident.push(c);
+        }
+        if !is_ident(&ident) {
+            return Err(anyhow!(
+                "expected identifier in cfg expression, got {:?}",
+                ident
+            ));
+        }
+        Ok(ident)
+    }
+
+    fn expect(chars: &mut Peekable<Chars>, e: char) -> Result<()> {
+        skip_ws(chars);
+        match chars.next() {
+            Some(c) if c == e => Ok(()),
+            Some(c) => Err(anyhow!("expected {:?} in cfg expression, got {:?}", e, c)),
+            None => Err(anyhow!("expected {:?} in cfg expression, got end", e)),
+        }
+    }
+
+    fn parse_expr(chars: &mut Peekable<Chars>) -> Result<CfgExpr> {
+        let ident = parse_ident(chars)?;
+        skip_ws(chars);
+        match (ident.as_str(), chars.peek()) {
+            ("all" | "any" | "not", Some('(')) => {
+                chars.next();
+                let mut es = Vec::new();
+                loop {
+                    skip_ws(chars);
+                    if chars.next_if_eq(&')').is_some() {
+                        break;
+                    }
+                    es.push(parse_expr(chars)?);
+                    skip_ws(chars);
+                    if chars.next_if_eq(&',').is_none() {
+                        expect(chars, ')')?;
+                        break;
+                    }
+                }
+                match ident.as_str() {
+                    "all" => Ok(CfgExpr::All(es)),
+                    "any" => Ok(CfgExpr::Any(es)),
+                    _ if es.len() == 1 => Ok(CfgExpr::Not(Box::new(es.remove(0)))),
+                    _ => Err(anyhow!("not() takes exactly one cfg expression")),
+                }
+            }
+            (_, Some('=')) => {
+                chars.next();
+                expect(chars, '"')?;
+                let mut val = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => val.push(c),
+                        None => return Err(anyhow!("unterminated string in cfg expression")),
+                    }
+                }
+                Ok(CfgExpr::Value(Cfg::KeyPair(ident, val)))
+            }
+            _ => Ok(CfgExpr::Value(Cfg::Name(ident))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml_edit::DocumentMut;
+
+    use super::Dep;
+    use super::api::CratesVersion;
+    use super::parse::parse_dep;
+
+    #[test]
+    fn default_features_spellings() {
+        let doc = "a = { version = \"1\", default-features = false }\n\
+                   b = { version = \"1\", default_features = false }\n\
+                   c = { version = \"1\" }\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        for (name, df) in [("a", Some(false)), ("b", Some(false)), ("c", None)] {
+            let d = Dep::from_toml(name, &doc[name]).unwrap();
+            assert_eq!(d.default_features, df, "{}", name);
+            assert!(d.extra.is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn parse_dep_names() {
+        let pd = parse_dep("serde_json@1:std").unwrap();
+        assert_eq!((pd.name.as_str(), pd.version.as_str()), ("serde_json", "1"));
+        for s in ["@1", ":derive", "ñame", "1abc@1", "!dev"] {
+            assert!(parse_dep(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn parse_dep_targets() {
+        for (s, name, target) in [
+            ("libc", "libc", ""),
+            ("pretty_assertions!dev", "pretty_assertions", "dev"),
+            ("cc@1!build", "cc", "build"),
+            ("libc!unix", "libc", "cfg(unix)"),
+            ("libc@0.2:std!unix", "libc", "cfg(unix)"),
+            (
+                "winapi@0.3!'x86_64-pc-windows-msvc'",
+                "winapi",
+                "x86_64-pc-windows-msvc",
+            ),
+            (
+                "nix!cfg(any(target_os=\"linux\",target_os=\"macos\"))",
+                "nix",
+                "cfg(any(target_os = \"linux\", target_os = \"macos\"))",
+            ),
+            ("nix!\"cfg(not(windows))\"", "nix", "cfg(not(windows))"),
+        ] {
+            let pd = parse_dep(s).unwrap();
+            assert_eq!(
+                (pd.name.as_str(), pd.target.as_str()),
+                (name, target),
+                "{}",
+                s
+            );
+        }
+        for s in [
+            "nix!cfg(target_os = linux)",
+            "nix!cfg(all(unix",
+            "nix!x86_64 linux",
+        ] {
+            assert!(parse_dep(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn cfg_expressions() {
+        use super::cfg::{Cfg, CfgExpr};
+
+        let name = |n: &str| CfgExpr::Value(Cfg::Name(n.to_string()));
+        let pair = |k: &str, v: &str| CfgExpr::Value(Cfg::KeyPair(k.to_string(), v.to_string()));
+        for (s, expr, shown) in [
+            ("unix", name("unix"), "unix"),
+            (
+                "target_os=\"linux\"",
+                pair("target_os", "linux"),
+                "target_os = \"linux\"",
+            ),
+            (
+                "feature = \"a b\"",
+                pair("feature", "a b"),
+                "feature = \"a b\"",
+            ),
+            (
+                "not(windows)",
+                CfgExpr::Not(Box::new(name("windows"))),
+                "not(windows)",
+            ),
+            ("all()", CfgExpr::All(vec![]), "all()"),
+            ("any(unix,)", CfgExpr::Any(vec![name("unix")]), "any(unix)"),
+            (
+                " all( unix , any(target_os = \"linux\", not(target_arch = \"x86\")) ) ",
+                CfgExpr::All(vec![
+                    name("unix"),
+                    CfgExpr::Any(vec![
+                        pair("target_os", "linux"),
+                        CfgExpr::Not(Box::new(pair("target_arch", "x86"))),
+                    ]),
+                ]),
+                "all(unix, any(target_os = \"linux\", not(target_arch = \"x86\")))",
+            ),
+        ] {
+            let e = CfgExpr::parse(s).unwrap();
+            assert_eq!(e, expr, "{}", s);
+            assert_eq!(e.to_string(), shown);
+            assert_eq!(CfgExpr::parse(shown).unwrap(), expr);
+        }
+        for s in [
+            "",
+            "target_os = linux",
+            "target_os = \"linux",
+            "all(unix",
+            "all(unix windows)",
+            "not(unix, windows)",
+            "not()",
+            "unix)",
+            "1unix",
+            "any(unix),",
+        ] {
+            assert!(CfgExpr::parse(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn platforms() {
+        use super::DType;
+        use super::cfg::Platform;
+
+        for (s, shown) in [
+            ("x86_64-pc-windows-msvc", "x86_64-pc-windows-msvc"),
+            ("'x86_64-pc-windows-msvc'", "x86_64-pc-windows-msvc"),
+            ("\"thumbv7em-none-eabihf\"", "thumbv7em-none-eabihf"),
+            ("unix", "cfg(unix)"),
+            ("'windows'", "cfg(windows)"),
+            ("cfg(any(unix,windows))", "cfg(any(unix, windows))"),
+            ("'cfg(target_os = \"linux\")'", "cfg(target_os = \"linux\")"),
+        ] {
+            let p = Platform::parse_dsl(s).unwrap();
+            assert_eq!(p.to_string(), shown, "{}", s);
+            assert_eq!(Platform::parse(shown).unwrap(), p);
+        }
+        // the dsl shorthand is not a triple
+        assert_eq!(
+            Platform::parse("unix").unwrap(),
+            Platform::Name("unix".to_string())
+        );
+        for s in [
+            "",
+            "cfg(all(unix",
+            "cfg(target_os = linux)",
+            "x86 64",
+            "a/b",
+        ] {
+            assert!(Platform::parse_dsl(s).is_err(), "{:?}", s);
+        }
+
+        let pd = parse_dep("nix!cfg(any(unix,windows))").unwrap();
+        assert_eq!(
+            DType::from(&pd.target).to_cargo_field(),
+            "target.'cfg(any(unix, windows))'.dependencies"
+        );
+    }
+
+    fn version(features: &[(&str, &[&str])]) -> CratesVersion {
+        CratesVersion {
+            features: features
+                .iter()
+                .map(|(f, en)| (f.to_string(), en.iter().map(|e| e.to_string()).collect()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn feature_closure() {
+        let cv = version(&[
+            ("default", &["std", "derive"]),
+            ("std", &["alloc"]),
+            ("alloc", &[]),
+            ("derive", &["dep:serde_derive"]),
+            ("full", &["std", "tokio/rt", "log?/std"]),
+            ("tokio", &["dep:tokio"]),
+            ("log", &["dep:log"]),
+            ("a", &["b"]),
+            ("b", &["a"]),
+        ]);
+        let closure = |roots: &[&str]| cv.feature_closure(roots).into_iter().collect::<Vec<_>>();
+
+        assert_eq!(closure(&["std"]), ["alloc", "std"]);
+        assert_eq!(closure(&["derive"]), ["dep:serde_derive", "derive"]);
+        // `tokio/rt` turns on the optional tokio, `log?/std` leaves log off
+        assert_eq!(
+            closure(&["full"]),
+            [
+                "alloc",
+                "dep:tokio",
+                "full",
+                "log?/std",
+                "std",
+                "tokio",
+                "tokio/rt"
+            ]
+        );
+        assert_eq!(closure(&["a"]), ["a", "b"]);
+        assert_eq!(closure(&["unknown"]), ["unknown"]);
+        assert!(closure(&[]).is_empty());
+    }
+
+    #[test]
+    fn redundant_features_and_dropped_defaults() {
+        let cv = version(&[
+            ("default", &["std", "derive"]),
+            ("std", &["alloc"]),
+            ("alloc", &[]),
+            ("derive", &["dep:serde_derive"]),
+            ("rc", &[]),
+            ("full", &["std", "tokio/rt"]),
+            ("tokio", &["dep:tokio"]),
+        ]);
+        let pairs = |v: Vec<(String, String)>| {
+            v.into_iter()
+                .map(|(f, by)| format!("{} by {}", f, by))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            pairs(cv.redundant_features(&["std", "alloc", "full", "rc"])),
+            ["std by full", "alloc by std"]
+        );
+        assert_eq!(
+            pairs(cv.redundant_features(&["tokio", "full"])),
+            ["tokio by full"]
+        );
+        assert!(cv.redundant_features(&["std", "derive", "rc"]).is_empty());
+
+        assert_eq!(cv.dropped_defaults::<&str>(&[]), ["alloc", "derive", "std"]);
+        assert_eq!(cv.dropped_defaults(&["std"]), ["derive"]);
+        assert_eq!(
+            cv.dropped_defaults(&["full", "derive"]),
+            Vec::<String>::new()
+        );
+        assert!(
+            version(&[("std", &[])])
+                .dropped_defaults(&["std"])
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use log::info;
+use serde::Deserialize;
+
+use crate::utils::ver::{Version, VersionReq};
+use crate::workspace::Workspace;
+
+/// Newest `Cargo.lock` format depi understands. Versions 3 and 4 are what
+/// cargo writes today, unversioned older files parse the same way.
+const MAX_LOCK_VERSION: u32 = 4;
+
+/// `Cargo.lock`, only the parts depi reads.
+#[derive(Debug, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub version: Option<u32>,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// `registry+<url>`, `sparse+<url>` or `git+<url>`, none for path and
+    /// workspace packages.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// `name`, with ` version` and ` (source)` added only when the name
+    /// alone is ambiguous.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+impl Lockfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let lock: Self = toml::from_str(&content)
+            .map_err(|e| anyhow!("invalid lock file {}: {}", path.display(), e))?;
+        if let Some(v) = lock.version
+            && v > MAX_LOCK_VERSION
+        {
+            return Err(anyhow!(
+                "{} is version {}, depi reads up to {}",
+                path.display(),
+                v,
+                MAX_LOCK_VERSION
+            ));
+        }
+        Ok(lock)
+    }
+    /// Lock file the package resolves with: the workspace root's for
+    /// members, else the one next to the manifest. `None` if there is none
+    /// yet.
+    pub fn find<P: AsRef<Path>>(manifest: P) -> Result<Option<Self>> {
+        let root = match Workspace::find(manifest.as_ref())? {
+            Some(ws) => ws.root,
+            None => manifest.as_ref().to_path_buf(),
+        };
+        let path = root.with_file_name("Cargo.lock");
+        if !path.is_file() {
+            info!("no lock file at {}", path.display());
+            return Ok(None);
+        }
+        Self::load(path).map(Some)
+    }
+
+    /// Version the crate `name` (not a rename of it) resolved to for
+    /// `package`, the newest one matching `req` when several are locked.
+    pub fn locked_version<S: AsRef<str>>(
+        &self,
+        package: Option<S>,
+        name: S,
+        req: S,
+    ) -> Option<&str> {
+        let name = name.as_ref();
+        // versions the lock records for this dependency of the package,
+        // `None` in the list when the name alone identifies it
+        let of_package = package.and_then(|pn| {
+            let p = self
+                .packages
+                .iter()
+                .find(|p| p.name == pn.as_ref() && p.source.is_none())?;
+            Some(
+                p.dependencies
+                    .iter()
+                    .filter_map(|d| {
+                        let mut parts = d.split_whitespace();
+                        (parts.next()? == name).then(|| parts.next())
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        });
+        let req = VersionReq::parse(req.as_ref()).ok();
+
+        self.packages
+            .iter()
+            .filter(|p| p.name == name)
+            .filter(|p| {
+                of_package
+                    .as_ref()
+                    .is_none_or(|vs| vs.iter().any(|v| v.is_none_or(|v| v == p.version)))
+            })
+            .filter_map(|p| Version::parse(&p.version).ok().map(|v| (v, p)))
+            .filter(|(v, _)| req.as_ref().is_none_or(|r| r.matches(v)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, p)| p.version.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const REGISTRY: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    fn load(name: &str, content: &str) -> Result<Lockfile> {
+        let path = env::temp_dir().join(format!("depi-lock-{}-{}", process::id(), name));
+        fs::write(&path, content).unwrap();
+        let lock = Lockfile::load(&path);
+        fs::remove_file(path).unwrap();
+        lock
+    }
+    fn lock(version: &str, packages: &[(&str, &str, bool, &[&str])]) -> String {
+        let mut s = format!("# generated by cargo\n{}", version);
+        for (name, ver, registry, deps) in packages {
+            s += &format!(
+                "\n[[package]]\nname = \"{}\"\nversion = \"{}\"\n",
+                name, ver
+            );
+            if *registry {
+                s += &format!("source = \"{}\"\n", REGISTRY);
+            }
+            if !deps.is_empty() {
+                s += &format!("dependencies = {:?}\n", deps);
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn versions() {
+        let packages: &[(&str, &str, bool, &[&str])] = &[
+            ("a", "0.1.0", false, &["serde"]),
+            ("serde", "1.0.200", true, &[]),
+        ];
+        for (name, version) in [("v3", "version = 3\n"), ("v4", "version = 4\n"), ("v1", "")] {
+            let l = load(name, &lock(version, packages)).unwrap();
+            assert_eq!(l.packages.len(), 2, "{}", name);
+            assert_eq!(l.packages[1].source.as_deref(), Some(REGISTRY));
+            assert_eq!(l.locked_version(Some("a"), "serde", "1"), Some("1.0.200"));
+        }
+
+        let err = load("v5", &lock("version = 5\n", packages)).unwrap_err();
+        assert!(
+            err.to_string().contains("is version 5, depi reads up to 4"),
+            "{}",
+            err
+        );
+        let err = load("invalid", "[[package]]\nname = 1\n").unwrap_err();
+        assert!(err.to_string().contains("invalid lock file"), "{}", err);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let rand_07 = format!("rand 0.7.3 ({})", REGISTRY);
+        let l = load(
+            "duplicates",
+            &lock(
+                "version = 4\n",
+                &[
+                    ("a", "0.1.0", false, &[&rand_07, "serde"]),
+                    ("b", "0.1.0", false, &["rand 0.8.5", "serde"]),
+                    ("c", "0.1.0", false, &[]),
+                    ("rand", "0.7.3", true, &[]),
+                    ("rand", "0.8.5", true, &[]),
+                    ("serde", "1.0.200", true, &[]),
+                ],
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(l.locked_version(Some("a"), "rand", "*"), Some("0.7.3"));
+        assert_eq!(l.locked_version(Some("b"), "rand", "*"), Some("0.8.5"));
+        assert_eq!(l.locked_version(Some("a"), "rand", "0.8"), None);
+        // not a dependency of c
+        assert_eq!(l.locked_version(Some("c"), "rand", "*"), None);
+        // no package to go by: the newest matching one
+        assert_eq!(l.locked_version(None, "rand", "*"), Some("0.8.5"));
+        assert_eq!(l.locked_version(None, "rand", "0.7"), Some("0.7.3"));
+        assert_eq!(
+            l.locked_version(Some("unknown"), "rand", "*"),
+            Some("0.8.5")
+        );
+        assert_eq!(l.locked_version(Some("b"), "serde", "1.0"), Some("1.0.200"));
+        assert_eq!(l.locked_version(Some("b"), "tokio", "*"), None);
+    }
+}
@@ -1,8 +1,13 @@
 mod cargo;
 mod commands;
+mod config;
 mod dep;
+mod lockfile;
+mod manifest;
+mod registry;
 mod storage;
 mod utils;
+mod workspace;
 
 use anyhow::Result;
 
@@ -0,0 +1,848 @@
+use std::{fmt, fs, path::Path};
+
+use anyhow::{Result, anyhow};
+use log::info;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Key, Table, TableLike, Value};
+
+use crate::dep::{DKind, DType, Dep, cfg::Platform};
+
+/// Cargo.toml document which keeps comments, key order and formatting of
+/// everything it does not touch.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    doc: DocumentMut,
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        info!("parsing {} file...", path.as_ref().display());
+        let content = fs::read_to_string(path)?;
+        let m = Self::parse(content)?;
+        info!("parsed successfully");
+        Ok(m)
+    }
+    pub fn parse<S: AsRef<str>>(s: S) -> Result<Self> {
+        Ok(Self {
+            doc: s.as_ref().parse::<DocumentMut>()?,
+        })
+    }
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        info!("saving changes to {}...", path.as_ref().display());
+        fs::write(path, self.doc.to_string())?;
+        Ok(())
+    }
+
+    pub fn set_package_field<S: AsRef<str>>(&mut self, key: S, val: S) {
+        let package = self
+            .doc
+            .entry("package")
+            .or_insert_with(|| Item::Table(Table::new()));
+        package[key.as_ref()] = toml_edit::value(val.as_ref());
+    }
+
+    /// `package.<key>` if it is a plain string, e.g. not inherited with
+    /// `{ workspace = true }`.
+    pub fn package_field<S: AsRef<str>>(&self, key: S) -> Option<String> {
+        self.doc
+            .get("package")?
+            .get(key.as_ref())?
+            .as_str()
+            .map(|v| v.to_string())
+    }
+    pub fn rust_version(&self) -> Option<String> {
+        self.package_field("rust-version")
+    }
+    /// Whether `package.<key>` is inherited with `{ workspace = true }`.
+    pub fn inherits_package_field<S: AsRef<str>>(&self, key: S) -> bool {
+        self.doc
+            .get("package")
+            .and_then(|p| p.get(key.as_ref()))
+            .and_then(|v| v.get("workspace"))
+            .and_then(|w| w.as_bool())
+            == Some(true)
+    }
+    /// `workspace.package.<key>`, what members inheriting `package.<key>` get.
+    pub fn workspace_package_field<S: AsRef<str>>(&self, key: S) -> Option<String> {
+        self.doc
+            .get("workspace")?
+            .get("package")?
+            .get(key.as_ref())?
+            .as_str()
+            .map(|v| v.to_string())
+    }
+
+    pub fn is_workspace(&self) -> bool {
+        self.doc.get("workspace").is_some_and(|w| w.is_table_like())
+    }
+    /// Workspace root without a package of its own.
+    pub fn is_virtual(&self) -> bool {
+        self.is_workspace() && self.doc.get("package").is_none()
+    }
+    /// String array `workspace.<key>`, e.g. `members`.
+    pub fn workspace_list<S: AsRef<str>>(&self, key: S) -> Vec<String> {
+        self.doc
+            .get("workspace")
+            .and_then(|w| w.get(key.as_ref()))
+            .and_then(|l| l.as_array())
+            .map(|l| {
+                l.iter()
+                    .filter_map(|v| v.as_str().map(|v| v.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Every dependency table of the package, target-specific ones included.
+    /// `[workspace.dependencies]` is left out, see `all_dtypes`.
+    pub fn dtypes(&self) -> Vec<DType> {
+        let mut res = DKind::ALL
+            .into_iter()
+            .map(DType::from)
+            .filter(|dtype| self.deps(dtype).is_some())
+            .collect::<Vec<_>>();
+
+        if let Some(targets) = self.doc.get("target").and_then(|t| t.as_table_like()) {
+            for (platform, t) in targets.iter() {
+                for kind in DKind::ALL {
+                    if t.get(kind.to_cargo_field())
+                        .and_then(|d| d.as_table_like())
+                        .is_some()
+                    {
+                        res.push(DType::Target(platform.to_string(), kind));
+                    }
+                }
+            }
+        }
+        res
+    }
+    /// `dtypes` and `[workspace.dependencies]` of a workspace root, for
+    /// reading and bumping versions. Removing from the latter would break
+    /// the members inheriting its entries.
+    pub fn all_dtypes(&self) -> Vec<DType> {
+        let mut res = self.dtypes();
+        if self.deps(&DType::Workspace).is_some() {
+            res.push(DType::Workspace);
+        }
+        res
+    }
+
+    pub fn deps(&self, dtype: &DType) -> Option<&dyn TableLike> {
+        let item = match dtype {
+            DType::Target(platform, kind) => self
+                .doc
+                .get("target")?
+                .get(platform)?
+                .get(kind.to_cargo_field())?,
+            DType::Workspace => self.doc.get("workspace")?.get("dependencies")?,
+            dtype => self.doc.get(dtype.kind().to_cargo_field())?,
+        };
+        item.as_table_like()
+    }
+    fn deps_mut(&mut self, dtype: &DType) -> Option<&mut dyn TableLike> {
+        let item = match dtype {
+            // `Item::get_mut` would create the missing tables
+            DType::Target(platform, kind) => self
+                .doc
+                .get_mut("target")?
+                .as_table_like_mut()?
+                .get_mut(platform)?
+                .as_table_like_mut()?
+                .get_mut(kind.to_cargo_field())?,
+            DType::Workspace => self
+                .doc
+                .get_mut("workspace")?
+                .as_table_like_mut()?
+                .get_mut("dependencies")?,
+            dtype => self.doc.get_mut(dtype.kind().to_cargo_field())?,
+        };
+        item.as_table_like_mut()
+    }
+    fn deps_or_insert(&mut self, dtype: &DType) -> Result<&mut dyn TableLike> {
+        let parent = match dtype {
+            DType::Target(platform, _) => {
+                let target = implicit_table(self.doc.as_table_mut(), "target")?;
+                let platform = same_platform_key(target, platform);
+                implicit_table(target, &platform)?
+            }
+            DType::Workspace => implicit_table(self.doc.as_table_mut(), "workspace")?,
+            _ => self.doc.as_table_mut() as &mut dyn TableLike,
+        };
+        parent
+            .entry(dtype.kind().to_cargo_field())
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_like_mut()
+            .ok_or(anyhow!("{} is not a table", dtype.to_cargo_field()))
+    }
+    /// Drops the dependency table if it is empty, together with the
+    /// `target.<platform>` and `target` tables left empty by that.
+    fn remove_empty(&mut self, dtype: &DType) {
+        if self.deps(dtype).is_none_or(|deps| !deps.is_empty()) {
+            return;
+        }
+        let DType::Target(platform, kind) = dtype else {
+            match dtype {
+                DType::Workspace => {
+                    if let Some(ws) = self
+                        .doc
+                        .get_mut("workspace")
+                        .and_then(|w| w.as_table_like_mut())
+                    {
+                        ws.remove("dependencies");
+                    }
+                }
+                _ => {
+                    self.doc.remove(dtype.kind().to_cargo_field());
+                }
+            }
+            return;
+        };
+        let Some(targets) = self
+            .doc
+            .get_mut("target")
+            .and_then(|t| t.as_table_like_mut())
+        else {
+            return;
+        };
+        if let Some(t) = targets
+            .get_mut(platform)
+            .and_then(|t| t.as_table_like_mut())
+        {
+            t.remove(kind.to_cargo_field());
+            if t.is_empty() {
+                targets.remove(platform);
+            }
+        }
+        if targets.is_empty() {
+            self.doc.remove("target");
+        }
+    }
+    pub fn get_deps(&self, dtype: &DType) -> Result<Vec<Dep>> {
+        match self.deps(dtype) {
+            Some(deps) => deps.iter().map(|(k, v)| Dep::from_toml(k, v)).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+    pub fn get_dep<S: AsRef<str>>(&self, dtype: &DType, name: S) -> Result<Option<Dep>> {
+        let name = name.as_ref();
+        match self.deps(dtype).and_then(|deps| deps.get(name)) {
+            Some(item) => Ok(Some(Dep::from_toml(name, item)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts (or replaces) the dependency entry, creating the field table
+    /// if needed. Keeps the table sorted if it was sorted before.
+    pub fn insert_dep(&mut self, dtype: &DType, dep: &Dep) -> Result<()> {
+        let deps = self.deps_or_insert(dtype)?;
+
+        let was_sorted = is_sorted(deps);
+        let (name, mut attrs) = dep.to_toml();
+        match deps.get_mut(&name) {
+            // tables keep their style, comments and the keys that stay
+            Some(old) if old.is_table_like() => {
+                update_entry(old, &attrs);
+                return Ok(());
+            }
+            Some(Item::Value(old)) => {
+                if let Item::Value(new) = &mut attrs {
+                    *new.decor_mut() = old.decor().clone();
+                }
+            }
+            _ => {}
+        }
+        deps.insert(&name, attrs);
+        if was_sorted {
+            deps.sort_values();
+        }
+        Ok(())
+    }
+    /// Removes the dependency entry and the whole field table if it becomes
+    /// empty.
+    pub fn remove_dep<S: AsRef<str>>(&mut self, dtype: &DType, name: S) -> Result<Option<Dep>> {
+        let name = name.as_ref();
+        let Some(deps) = self.deps_mut(dtype) else {
+            return Ok(None);
+        };
+        let Some(item) = deps.remove(name) else {
+            return Ok(None);
+        };
+        self.remove_empty(dtype);
+        Ok(Some(Dep::from_toml(name, &item)?))
+    }
+    /// Rewrites only the version of an existing dependency entry.
+    pub fn set_dep_version<S: AsRef<str>>(&mut self, dtype: &DType, name: S, ver: S) -> Result<()> {
+        let name = name.as_ref();
+        let item = self
+            .deps_mut(dtype)
+            .and_then(|deps| deps.get_mut(name))
+            .ok_or(anyhow!("{} not found in {}", name, dtype.to_cargo_field()))?;
+
+        match item {
+            Item::Value(Value::String(_)) => set_value_keep_decor(item, ver.as_ref()),
+            item => match item.as_table_like_mut() {
+                Some(attrs) => match attrs.get_mut("version") {
+                    Some(v) => set_value_keep_decor(v, ver.as_ref()),
+                    None => {
+                        attrs.insert("version", toml_edit::value(ver.as_ref()));
+                    }
+                },
+                None => return Err(anyhow!("parse error: incorrect attrs type")),
+            },
+        }
+        Ok(())
+    }
+    /// Appends the features missing from the `features` array of the entry,
+    /// turning a `name = "1"` entry into an inline table if needed.
+    /// Returns the ones actually added.
+    pub fn add_dep_features<S: AsRef<str>>(
+        &mut self,
+        dtype: &DType,
+        name: S,
+        features: &[String],
+    ) -> Result<Vec<String>> {
+        let name = name.as_ref();
+        let item = self
+            .deps_mut(dtype)
+            .and_then(|deps| deps.get_mut(name))
+            .ok_or(anyhow!("{} not found in {}", name, dtype.to_cargo_field()))?;
+
+        if let Item::Value(Value::String(ver)) = item {
+            let mut t = InlineTable::new();
+            t.insert("version", Value::from(ver.value().as_str()));
+            *t.decor_mut() = ver.decor().clone();
+            *item = Item::Value(Value::InlineTable(t));
+        }
+        keep_closing_space(item, |item| {
+            let attrs = item
+                .as_table_like_mut()
+                .ok_or(anyhow!("parse error: incorrect attrs type"))?;
+            let arr = attrs
+                .entry("features")
+                .or_insert(toml_edit::value(Array::new()))
+                .as_array_mut()
+                .ok_or(anyhow!("features of {} is not an array", name))?;
+
+            let mut added = Vec::new();
+            for f in features {
+                if !arr.iter().any(|v| v.as_str() == Some(f)) {
+                    arr.push(f.as_str());
+                    added.push(f.to_string());
+                }
+            }
+            Ok(added)
+        })
+    }
+    /// Drops the features from the `features` array of the entry, the array
+    /// itself if it gets empty and the inline table if only the version is
+    /// left. Returns the ones actually removed.
+    pub fn remove_dep_features<S: AsRef<str>>(
+        &mut self,
+        dtype: &DType,
+        name: S,
+        features: &[String],
+    ) -> Result<Vec<String>> {
+        let name = name.as_ref();
+        let item = self
+            .deps_mut(dtype)
+            .and_then(|deps| deps.get_mut(name))
+            .ok_or(anyhow!("{} not found in {}", name, dtype.to_cargo_field()))?;
+        let Some(attrs) = item.as_table_like_mut() else {
+            return Ok(Vec::new());
+        };
+        let Some(arr) = attrs.get_mut("features").and_then(|f| f.as_array_mut()) else {
+            return Ok(Vec::new());
+        };
+
+        let mut removed = Vec::new();
+        for f in features {
+            let pos = arr.iter().position(|v| v.as_str() == Some(f));
+            if let Some(i) = pos {
+                arr.remove(i);
+                removed.push(f.to_string());
+            }
+        }
+        let mut suffix = None;
+        if arr.is_empty() {
+            suffix = attrs
+                .remove("features")
+                .and_then(|f| f.as_value().and_then(|v| v.decor().suffix().cloned()));
+        }
+
+        if let Item::Value(Value::InlineTable(t)) = item
+            && let Some(suffix) = suffix.filter(|s| s.as_str() != Some(""))
+            && let Some((_, last)) = t.iter_mut().last()
+        {
+            last.decor_mut().set_suffix(suffix);
+        }
+        if let Item::Value(Value::InlineTable(t)) = item
+            && t.len() == 1
+            && let Some(ver) = t.get("version").and_then(|v| v.as_str())
+        {
+            let mut nv = Value::from(ver);
+            *nv.decor_mut() = t.decor().clone();
+            *item = Item::Value(nv);
+        }
+        Ok(removed)
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+/// Makes the table entry `old` hold what `new` (a version string or an
+/// inline table) does, rewriting only the keys whose values change.
+fn update_entry(old: &mut Item, new: &Item) {
+    let mut keys = match new {
+        Item::Value(Value::InlineTable(t)) => t
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect::<Vec<_>>(),
+        Item::Value(v) => vec![("version".to_string(), v.clone())],
+        _ => return,
+    };
+    keep_closing_space(old, |old| {
+        let Some(attrs) = old.as_table_like_mut() else {
+            return;
+        };
+
+        for (k, _) in keys.iter_mut() {
+            // the old spelling cargo still accepts
+            if k == "default-features" && attrs.contains_key("default_features") {
+                *k = "default_features".to_string();
+            }
+        }
+        let stale = attrs
+            .iter()
+            .map(|(k, _)| k.to_string())
+            .filter(|k| !keys.iter().any(|(nk, _)| nk == k))
+            .collect::<Vec<_>>();
+        for k in stale {
+            attrs.remove(&k);
+        }
+        for (k, mut v) in keys {
+            match attrs.get_mut(&k) {
+                Some(Item::Value(ov)) => {
+                    if !same_value(ov, &v) {
+                        *v.decor_mut() = ov.decor().clone();
+                        *ov = v;
+                    }
+                }
+                Some(item) => *item = Item::Value(v),
+                None => {
+                    v.decor_mut().clear();
+                    attrs.insert(&k, Item::Value(v));
+                }
+            }
+        }
+    });
+}
+
+/// Runs `f` on the entry, moving the space an inline table keeps before its
+/// `}` (`{ a = 1 }`) from the last value to whichever value is last after.
+fn keep_closing_space<R, F: FnOnce(&mut Item) -> R>(item: &mut Item, f: F) -> R {
+    let mut suffix = None;
+    if let Item::Value(Value::InlineTable(t)) = item
+        && let Some((_, last)) = t.iter_mut().last()
+    {
+        suffix = last.decor().suffix().cloned();
+        last.decor_mut().set_suffix("");
+    }
+    let res = f(item);
+    if let Item::Value(Value::InlineTable(t)) = item
+        && let Some((_, last)) = t.iter_mut().last()
+        && let Some(suffix) = suffix
+    {
+        last.decor_mut().set_suffix(suffix);
+    }
+    res
+}
+
+/// Whether both values hold the same data, however they are written.
+fn same_value(a: &Value, b: &Value) -> bool {
+    let parse = |v: &Value| {
+        format!("v = {}", v.to_string().trim())
+            .parse::<toml::Table>()
+            .ok()
+    };
+    parse(a).is_some_and(|a| Some(a) == parse(b))
+}
+
+fn set_value_keep_decor(item: &mut Item, val: &str) {
+    let mut nv = Value::from(val);
+    if let Item::Value(old) = item {
+        *nv.decor_mut() = old.decor().clone();
+    }
+    *item = Item::Value(nv);
+}
+
+/// Existing key of `target` written differently but meaning the same
+/// platform, e.g. `cfg(any(unix,windows))` for `cfg(any(unix, windows))`.
+fn same_platform_key(target: &dyn TableLike, platform: &str) -> String {
+    let Ok(p) = Platform::parse(platform) else {
+        return platform.to_string();
+    };
+    target
+        .iter()
+        .map(|(k, _)| k)
+        .find(|k| Platform::parse(k).is_ok_and(|kp| kp == p))
+        .unwrap_or(platform)
+        .to_string()
+}
+
+/// Child table which is not written as its own header, e.g. `target` in
+/// `[target.'cfg(unix)'.dependencies]`.
+fn implicit_table<'a>(parent: &'a mut dyn TableLike, key: &str) -> Result<&'a mut dyn TableLike> {
+    // cargo docs quote `cfg(...)` platforms with literal strings
+    let bare = key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    let fkey = match Key::parse(&format!("'{}'", key)) {
+        Ok(mut keys) if !bare && keys.len() == 1 && !key.contains('\'') => keys.remove(0),
+        _ => Key::new(key),
+    };
+    parent
+        .entry_format(&fkey)
+        .or_insert_with(|| {
+            let mut t = Table::new();
+            t.set_implicit(true);
+            Item::Table(t)
+        })
+        .as_table_like_mut()
+        .ok_or(anyhow!("{} is not a table", key))
+}
+
+fn is_sorted(t: &dyn TableLike) -> bool {
+    let keys = t.iter().map(|(k, _)| k).collect::<Vec<_>>();
+    keys.windows(2).all(|w| w[0] <= w[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::DSource;
+
+    /// Manifests written the ways found in the wild: comments everywhere,
+    /// dotted and `[dependencies.x]` tables, targets, workspaces.
+    const MANIFESTS: &[&str] = &[
+        r#"[package]
+name = "cli"
+version = "0.3.1"
+edition = "2021"
+rust-version = "1.70"
+
+[dependencies]
+# async runtime
+tokio = { version = "1.38", features = ["rt-multi-thread", "macros"] }  # keep in sync
+serde = { version = "1.0", features = ["derive"], optional = true }
+anyhow = "1"
+log = { version = "0.4" }
+regex = {version="1.10",default-features=false,features=["std"]}
+local = { path = "../local" }
+git-dep = { git = "https://github.com/a/b", branch = "main" }
+
+[dependencies.clap]
+# the cli
+version = "4.5"  # pinned for msrv
+features = ["derive", "env"]
+optional = true
+public = true
+
+[dev-dependencies]
+pretty_assertions = "1.4.0"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[target."cfg(windows)".dependencies.windows-sys]
+version = "0.52"
+features = [
+    "Win32_Foundation",
+    "Win32_System_Console",
+]
+
+[features]
+default = ["serde"]
+"#,
+        r#"[workspace]
+members = ["crates/*"]
+resolver = "2"
+
+[workspace.dependencies]
+serde = { version = "1.0.200", default-features = false }
+tokio = "1.40"
+
+[workspace.package]
+rust-version = "1.74"
+"#,
+        r#"[package]
+name = "renamed"
+version = "0.1.0"
+
+[build-dependencies]
+cc = "1.0"
+
+[dependencies]
+json = { package = "serde_json", version = "1.0.100", registry = "internal" }
+serde = { workspace = true, features = ["derive"] }
+old = { version = "0.1", default_features = false }
+"#,
+    ];
+
+    #[test]
+    fn parse_and_save_round_trips() {
+        for m in MANIFESTS {
+            assert_eq!(Manifest::parse(m).unwrap().to_string(), *m);
+        }
+    }
+
+    #[test]
+    fn reinserting_entries_changes_nothing() {
+        for m in MANIFESTS {
+            let mut content = Manifest::parse(m).unwrap();
+            for dtype in content.all_dtypes() {
+                for d in content.get_deps(&dtype).unwrap() {
+                    content.insert_dep(&dtype, &d).unwrap();
+                }
+            }
+            assert_eq!(content.to_string(), *m);
+        }
+    }
+
+    #[test]
+    fn table_entry_is_edited_in_place() {
+        let mut content = Manifest::parse(MANIFESTS[0]).unwrap();
+        let mut d = content.get_dep(&DType::Normal, "clap").unwrap().unwrap();
+        d.version = "4.6".to_string();
+        d.features = Some(vec!["derive".to_string()]);
+        content.insert_dep(&DType::Normal, &d).unwrap();
+
+        let expected = MANIFESTS[0].replace(
+            "version = \"4.5\"  # pinned for msrv\nfeatures = [\"derive\", \"env\"]\n",
+            "version = \"4.6\"  # pinned for msrv\nfeatures = [\"derive\"]\n",
+        );
+        assert_eq!(content.to_string(), expected);
+
+        let platform = DType::Target("cfg(windows)".to_string(), DKind::Normal);
+        let mut d = content.get_dep(&platform, "windows-sys").unwrap().unwrap();
+        d.version = "0.59".to_string();
+        content.insert_dep(&platform, &d).unwrap();
+        assert!(content.to_string().contains(
+            "[target.\"cfg(windows)\".dependencies.windows-sys]\nversion = \"0.59\"\nfeatures = [\n    \"Win32_Foundation\","
+        ));
+    }
+
+    #[test]
+    fn inline_entry_is_edited_in_place() {
+        let mut content = Manifest::parse(MANIFESTS[0]).unwrap();
+
+        let mut d = content.get_dep(&DType::Normal, "regex").unwrap().unwrap();
+        d.version = "1.11".to_string();
+        d.optional = Some(true);
+        content.insert_dep(&DType::Normal, &d).unwrap();
+        let mut d = content.get_dep(&DType::Normal, "tokio").unwrap().unwrap();
+        d.features = None;
+        content.insert_dep(&DType::Normal, &d).unwrap();
+        let mut d = content.get_dep(&DType::Normal, "log").unwrap().unwrap();
+        d.default_features = Some(false);
+        content.insert_dep(&DType::Normal, &d).unwrap();
+
+        let m = content.to_string();
+        assert!(m.contains(
+            "regex = {version=\"1.11\",default-features=false,features=[\"std\"], optional = true}\n"
+        ));
+        assert!(m.contains("# async runtime\ntokio = { version = \"1.38\" }  # keep in sync\n"));
+        assert!(m.contains("log = { version = \"0.4\", default-features = false }\n"));
+    }
+
+    #[test]
+    fn entries_keep_their_spelling() {
+        let mut content = Manifest::parse(MANIFESTS[2]).unwrap();
+        let mut d = content.get_dep(&DType::Normal, "old").unwrap().unwrap();
+        d.version = "0.2".to_string();
+        content.insert_dep(&DType::Normal, &d).unwrap();
+        let mut d = content.get_dep(&DType::Normal, "json").unwrap().unwrap();
+        assert_eq!(d.source, DSource::Registry("internal".to_string()));
+        d.version = "1.0.140".to_string();
+        content.insert_dep(&DType::Normal, &d).unwrap();
+
+        let m = content.to_string();
+        assert!(m.contains("old = { version = \"0.2\", default_features = false }\n"));
+        assert!(m.contains(
+            "json = { package = \"serde_json\", version = \"1.0.140\", registry = \"internal\" }\n"
+        ));
+    }
+
+    #[test]
+    fn target_tables_are_found() {
+        let content = Manifest::parse(MANIFESTS[0]).unwrap();
+        assert_eq!(
+            content.dtypes(),
+            [
+                DType::Normal,
+                DType::Dev,
+                DType::Target("cfg(unix)".to_string(), DKind::Normal),
+                DType::Target("cfg(windows)".to_string(), DKind::Normal),
+            ]
+        );
+        let content = Manifest::parse(MANIFESTS[1]).unwrap();
+        assert!(content.dtypes().is_empty());
+        assert_eq!(content.all_dtypes(), [DType::Workspace]);
+    }
+
+    #[test]
+    fn target_tables_are_created_and_dropped() {
+        let mut content = Manifest::parse(MANIFESTS[2]).unwrap();
+        let d = Dep {
+            name: "libc".to_string(),
+            version: "0.2".to_string(),
+            ..Default::default()
+        };
+        let unix = DType::Target("cfg(unix)".to_string(), DKind::Normal);
+        let unix_dev = DType::Target("cfg(unix)".to_string(), DKind::Dev);
+        content.insert_dep(&unix, &d).unwrap();
+        content.insert_dep(&unix_dev, &d).unwrap();
+        let expected = format!(
+            "{}\n[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n\n[target.'cfg(unix)'.dev-dependencies]\nlibc = \"0.2\"\n",
+            MANIFESTS[2]
+        );
+        assert_eq!(content.to_string(), expected);
+
+        content.remove_dep(&unix, "libc").unwrap().unwrap();
+        assert!(
+            !content
+                .to_string()
+                .contains("[target.'cfg(unix)'.dependencies]")
+        );
+        content.remove_dep(&unix_dev, "libc").unwrap().unwrap();
+        assert_eq!(content.to_string(), MANIFESTS[2]);
+
+        // the last entry of an existing target goes with its tables
+        let mut content = Manifest::parse(MANIFESTS[0]).unwrap();
+        let windows = DType::Target("cfg(windows)".to_string(), DKind::Normal);
+        content
+            .remove_dep(&windows, "windows-sys")
+            .unwrap()
+            .unwrap();
+        let m = content.to_string();
+        assert!(!m.contains("windows"));
+        assert!(m.contains("[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n"));
+        assert_eq!(content.dtypes().len(), 3);
+    }
+
+    #[test]
+    fn new_entries_keep_the_table_sorted() {
+        let mut content = Manifest::parse(MANIFESTS[1]).unwrap();
+        let d = Dep {
+            name: "anyhow".to_string(),
+            version: "1".to_string(),
+            ..Default::default()
+        };
+        content.insert_dep(&DType::Workspace, &d).unwrap();
+        assert!(content.to_string().contains(
+            "[workspace.dependencies]\nanyhow = \"1\"\nserde = { version = \"1.0.200\", default-features = false }\n"
+        ));
+    }
+
+    /// One entry per key cargo knows in a dependency, all at version `1.0`.
+    const KEYED_ENTRIES: &[&str] = &[
+        r#"a = { version = "1.0", path = "../a" }"#,
+        r#"a = { version = "1.0", git = "https://github.com/a/a", branch = "next" }"#,
+        r#"a = { version = "1.0", git = "https://github.com/a/a", tag = "v1.0.0" }"#,
+        r#"a = { version = "1.0", git = "https://github.com/a/a", rev = "0a1b2c3" }"#,
+        r#"a = { version = "1.0", registry = "internal" }"#,
+        r#"a = { version = "1.0", registry-index = "sparse+https://example.com/index/" }"#,
+        r#"a = { version = "1.0", package = "real-a" }"#,
+        r#"a = { version = "1.0", features = ["x", "y"] }"#,
+        r#"a = { version = "1.0", optional = true }"#,
+        r#"a = { version = "1.0", default-features = false }"#,
+        r#"a = { version = "1.0", default_features = false }"#,
+        r#"a = { version = "1.0", artifact = ["bin", "cdylib"] }"#,
+        r#"a = { version = "1.0", artifact = "bin", lib = true }"#,
+        r#"a = { version = "1.0", artifact = "bin", target = "wasm32-unknown-unknown" }"#,
+        r#"a = { version = "1.0", public = true }"#,
+    ];
+
+    #[test]
+    fn every_key_survives_a_version_change() {
+        for entry in KEYED_ENTRIES {
+            let inline = format!("[dependencies]\n{}\n", entry);
+            // the same entry as a `[dependencies.a]` table
+            let mut doc = inline.parse::<DocumentMut>().unwrap();
+            let t = doc["dependencies"]["a"]
+                .as_inline_table()
+                .unwrap()
+                .clone()
+                .into_table();
+            doc["dependencies"]["a"] = Item::Table(t);
+            let table = doc.to_string();
+
+            for m in [inline, table] {
+                let expected = m.replace("\"1.0\"", "\"1.1\"");
+
+                let mut content = Manifest::parse(&m).unwrap();
+                let mut d = content.get_dep(&DType::Normal, "a").unwrap().unwrap();
+                d.version = "1.1".to_string();
+                content.insert_dep(&DType::Normal, &d).unwrap();
+                assert_eq!(content.to_string(), expected);
+
+                let mut content = Manifest::parse(&m).unwrap();
+                content.set_dep_version(&DType::Normal, "a", "1.1").unwrap();
+                assert_eq!(content.to_string(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn every_key_survives_to_toml() {
+        for entry in KEYED_ENTRIES {
+            let doc = entry.parse::<DocumentMut>().unwrap();
+            let d = Dep::from_toml("a", &doc["a"]).unwrap();
+            let (name, item) = d.to_toml();
+            assert_eq!(name, "a");
+            // only the old `default_features` spelling is normalized
+            let expected = doc["a"]
+                .to_string()
+                .replace("default_features", "default-features");
+            let written = item.as_value().unwrap();
+            let old = expected.trim().parse::<Value>().unwrap();
+            assert!(same_value(written, &old), "{} became {}", entry, written);
+        }
+    }
+
+    #[test]
+    fn features_round_trip() {
+        for m in MANIFESTS {
+            let mut content = Manifest::parse(m).unwrap();
+            for dtype in content.all_dtypes() {
+                for d in content.get_deps(&dtype).unwrap() {
+                    let f = vec!["extra-feature".to_string()];
+                    assert_eq!(content.add_dep_features(&dtype, &d.name, &f).unwrap(), f);
+                    assert_eq!(content.remove_dep_features(&dtype, &d.name, &f).unwrap(), f);
+                }
+            }
+            // a bare `{ version = .. }` left over collapses to the string
+            let m = m.replace("log = { version = \"0.4\" }", "log = \"0.4\"");
+            assert_eq!(content.to_string(), m);
+        }
+    }
+
+    #[test]
+    fn closing_space_stays_last() {
+        let mut content = Manifest::parse(
+            "[dependencies]\na = { version = \"1\" }\nb = { version = \"1\", features = [\"x\"] }\n",
+        )
+        .unwrap();
+        let f = vec!["y".to_string()];
+        content.add_dep_features(&DType::Normal, "a", &f).unwrap();
+        content.add_dep_features(&DType::Normal, "b", &f).unwrap();
+        content.set_dep_version(&DType::Normal, "a", "2").unwrap();
+        assert_eq!(
+            content.to_string(),
+            "[dependencies]\na = { version = \"2\", features = [\"y\"] }\n\
+             b = { version = \"1\", features = [\"x\", \"y\"] }\n"
+        );
+    }
+}
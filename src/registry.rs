@@ -0,0 +1,1225 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
+
+use anyhow::{Result, anyhow};
+use futures::future::BoxFuture;
+use log::{info, warn};
+use reqwest::{StatusCode, header};
+use serde::Deserialize;
+use serde_json::Value as JValue;
+
+use crate::config::{self, CargoConfig, Source};
+use crate::dep::api::{CratesDep, CratesVersion};
+use crate::dep::parse;
+use crate::storage;
+use crate::utils::ver::Version;
+
+pub const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+pub const CRATES_IO_API: &str = "https://crates.io/";
+pub const USER_AGENT: &str = "depi/0.1.0";
+pub const SEARCH_PER_PAGE: usize = 10;
+
+/// Source of crate metadata.
+pub trait Registry: Send + Sync {
+    /// Name shown in messages, e.g. `crates.io` or the index url.
+    fn name(&self) -> &str;
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CratesDep>>;
+    /// One page (starting at 1) of crates matching the query.
+    fn search<'a>(&'a self, query: &'a str, page: usize) -> BoxFuture<'a, Result<SearchPage>> {
+        let _ = (query, page);
+        Box::pin(async move { Err(anyhow!("{} does not support search", self.name())) })
+    }
+    fn owners<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<Owner>>> {
+        let _ = name;
+        Box::pin(async move { Err(anyhow!("{} does not list owners", self.name())) })
+    }
+    /// Descriptive fields of the crate, the ones of its newest version where
+    /// they differ between versions.
+    fn metadata<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CrateMeta>> {
+        let _ = name;
+        Box::pin(async move { Err(anyhow!("{} has no crate metadata", self.name())) })
+    }
+}
+
+/// Where commands get their registries from: the cargo configuration, or
+/// fixtures in tests.
+pub trait RegistryProvider: Send + Sync {
+    /// Registry by its name in the cargo config, `None` for crates.io.
+    fn get(&self, name: Option<&str>) -> Result<Box<dyn Registry>>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchEntry {
+    pub name: String,
+    pub max_version: String,
+    /// Unknown for local registries.
+    pub downloads: Option<u64>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchPage {
+    pub crates: Vec<SearchEntry>,
+    /// Number of matches over all pages.
+    pub total: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Owner {
+    pub login: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CrateMeta {
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+}
+
+/// How network registries use the local cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Use cached data younger than `CACHE_TTL`, fetch otherwise.
+    #[default]
+    Ttl,
+    /// Never touch the network.
+    Offline,
+    /// Always fetch and overwrite the cache.
+    Refresh,
+}
+
+impl CacheMode {
+    pub fn from_flags(offline: bool, refresh: bool) -> Self {
+        if offline {
+            Self::Offline
+        } else if refresh {
+            Self::Refresh
+        } else {
+            Self::Ttl
+        }
+    }
+}
+
+pub const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Registries known to cargo for a project: crates.io and the ones from
+/// its `.cargo/config.toml` files, with source replacement applied.
+pub struct Registries {
+    config: CargoConfig,
+    cm: CacheMode,
+}
+
+impl Registries {
+    pub fn load<P: AsRef<Path>>(dir: P, cm: CacheMode) -> Result<Self> {
+        Ok(Self {
+            config: CargoConfig::load(dir)?,
+            cm,
+        })
+    }
+}
+
+impl RegistryProvider for Registries {
+    /// `DEPI_REGISTRY` replaces crates.io with:
+    /// - `sparse+<url>`: any sparse index
+    /// - `dir:<path>`: index files in a local directory
+    /// - `file://<path>`: local checkout of a git index
+    /// - `vendor:<path>`: crates unpacked by `cargo vendor`
+    /// - `fixture:<path>`: json fixture loaded into memory
+    fn get(&self, name: Option<&str>) -> Result<Box<dyn Registry>> {
+        if name.is_none()
+            && let Ok(spec) = env::var("DEPI_REGISTRY")
+        {
+            return from_spec(spec, self.cm);
+        }
+        let name = name.unwrap_or(config::CRATES_IO);
+        match self.config.source(name)? {
+            Source::CratesIo => Ok(Box::new(CratesIo::new(self.cm)?)),
+            Source::Index { url, token } => {
+                if url.starts_with("sparse+") {
+                    return Ok(Box::new(
+                        SparseRegistry::new(url, self.cm)?.with_token(token),
+                    ));
+                }
+                match git_checkout(&url) {
+                    Some(path) => Ok(Box::new(LocalRegistry::from_git_checkout(path)?)),
+                    None => Err(anyhow!(
+                        "index {} of {} is a git index, only sparse+ indexes and local \
+                         checkouts of git indexes (file://) are supported",
+                        url,
+                        name
+                    )),
+                }
+            }
+            Source::LocalRegistry(path) => Ok(Box::new(LocalRegistry::new(path)?)),
+            Source::Directory(path) => Ok(Box::new(DirectoryRegistry::new(path)?)),
+        }
+    }
+}
+
+fn from_spec<S: AsRef<str>>(spec: S, cm: CacheMode) -> Result<Box<dyn Registry>> {
+    let spec = spec.as_ref();
+    info!("using registry {:?}", spec);
+    if spec.is_empty() || spec == config::CRATES_IO {
+        Ok(Box::new(CratesIo::new(cm)?))
+    } else if spec.starts_with("sparse+") {
+        Ok(Box::new(SparseRegistry::new(spec, cm)?))
+    } else if let Some(path) = git_checkout(spec) {
+        Ok(Box::new(LocalRegistry::from_git_checkout(path)?))
+    } else if let Some(path) = spec.strip_prefix("dir:") {
+        Ok(Box::new(LocalRegistry::new(path)?))
+    } else if let Some(path) = spec.strip_prefix("vendor:") {
+        Ok(Box::new(DirectoryRegistry::new(path)?))
+    } else if let Some(path) = spec.strip_prefix("fixture:") {
+        Ok(Box::new(MemoryRegistry::from_file(path)?))
+    } else {
+        Err(anyhow!("unknown registry: {}", spec))
+    }
+}
+
+/// Directory of a `file://` index url, optionally `registry+` prefixed like
+/// in `Cargo.lock`. Git indexes have the same layout as sparse ones, so a
+/// checkout of one can be read as a local registry.
+fn git_checkout(url: &str) -> Option<PathBuf> {
+    let url = url.strip_prefix("registry+").unwrap_or(url);
+    url.strip_prefix("file://").map(PathBuf::from)
+}
+
+/// crates.io: metadata from its sparse index, search and owners from the
+/// web api.
+pub struct CratesIo {
+    index: SparseRegistry,
+    api: String,
+}
+
+impl CratesIo {
+    pub fn new(cm: CacheMode) -> Result<Self> {
+        Ok(Self {
+            index: SparseRegistry::new(CRATES_IO_INDEX, cm)?,
+            api: CRATES_IO_API.to_string(),
+        })
+    }
+    async fn get_json(&self, path: String) -> Result<JValue> {
+        if self.index.cm == CacheMode::Offline {
+            return Err(anyhow!("{} needs network, run without --offline", path));
+        }
+        let url = format!("{}{}", self.api, path);
+        let body = reqwest::Client::new()
+            .get(&url)
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(serde_json::from_str::<JValue>(&body)?)
+    }
+}
+
+impl Registry for CratesIo {
+    fn name(&self) -> &str {
+        "crates.io"
+    }
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CratesDep>> {
+        self.index.fetch(name)
+    }
+    fn search<'a>(&'a self, query: &'a str, page: usize) -> BoxFuture<'a, Result<SearchPage>> {
+        Box::pin(async move {
+            let obj = self
+                .get_json(format!(
+                    "api/v1/crates?q={}&page={}&per_page={}",
+                    url_encode(query),
+                    page,
+                    SEARCH_PER_PAGE
+                ))
+                .await?;
+
+            let mut res = SearchPage {
+                total: obj["meta"]["total"].as_u64().unwrap_or_default() as usize,
+                ..Default::default()
+            };
+            if let Some(JValue::Array(arr)) = obj.get("crates") {
+                for c in arr {
+                    let Some(name) = c["name"].as_str() else {
+                        continue;
+                    };
+                    res.crates.push(SearchEntry {
+                        name: name.to_string(),
+                        max_version: c["max_stable_version"]
+                            .as_str()
+                            .or(c["max_version"].as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                        downloads: c["downloads"].as_u64(),
+                        description: c["description"].as_str().map(|d| d.trim().to_string()),
+                    });
+                }
+            }
+            Ok(res)
+        })
+    }
+    fn owners<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<Owner>>> {
+        Box::pin(async move {
+            let obj = self
+                .get_json(format!("api/v1/crates/{}/owners", name))
+                .await?;
+            let mut res = Vec::new();
+            if let Some(JValue::Array(arr)) = obj.get("users") {
+                for u in arr {
+                    if let Some(login) = u["login"].as_str() {
+                        res.push(Owner {
+                            login: login.to_string(),
+                            name: u["name"].as_str().map(|n| n.to_string()),
+                        });
+                    }
+                }
+            }
+            Ok(res)
+        })
+    }
+    fn metadata<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CrateMeta>> {
+        Box::pin(async move {
+            let obj = self.get_json(format!("api/v1/crates/{}", name)).await?;
+            let c = &obj["crate"];
+            let get = |v: &JValue| v.as_str().map(|s| s.trim().to_string());
+
+            // versions come newest first, prefer the newest stable one
+            let newest = c["max_stable_version"]
+                .as_str()
+                .or(c["max_version"].as_str());
+            let license = obj["versions"].as_array().and_then(|vs| {
+                vs.iter()
+                    .find(|v| v["num"].as_str() == newest)
+                    .or(vs.first())
+                    .and_then(|v| get(&v["license"]))
+            });
+            Ok(CrateMeta {
+                description: get(&c["description"]),
+                license,
+                repository: get(&c["repository"]),
+                homepage: get(&c["homepage"]),
+                documentation: get(&c["documentation"]),
+            })
+        })
+    }
+}
+
+/// Registry speaking the sparse index protocol, index files are cached and
+/// revalidated with their ETag and Last-Modified.
+pub struct SparseRegistry {
+    url: String,
+    cm: CacheMode,
+    cache: storage::CrateCache,
+    /// Sent as `Authorization` to private registries.
+    token: Option<String>,
+}
+
+impl SparseRegistry {
+    /// `url` may be prefixed with `sparse+` like in cargo configs.
+    pub fn new<S: AsRef<str>>(url: S, cm: CacheMode) -> Result<Self> {
+        let url = Self::base_url(url);
+        let cache = storage::CrateCache::load(&url)?;
+        Ok(Self::with_cache(url, cm, cache))
+    }
+    /// Like `new`, with the index files cached in `cache`.
+    pub fn with_cache<S: AsRef<str>>(url: S, cm: CacheMode, cache: storage::CrateCache) -> Self {
+        Self {
+            url: Self::base_url(url),
+            cm,
+            cache,
+            token: None,
+        }
+    }
+    fn base_url<S: AsRef<str>>(url: S) -> String {
+        let url = url.as_ref();
+        let mut url = url.strip_prefix("sparse+").unwrap_or(url).to_string();
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        url
+    }
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Downloads the index file, revalidating `cached` with its ETag and
+    /// Last-Modified. Returns `None` if the cached copy is still current.
+    async fn download(
+        &self,
+        name: &str,
+        cached: Option<&storage::CacheEntry>,
+    ) -> Result<Option<storage::CacheEntry>> {
+        let url = format!("{}{}", self.url, index_path(name)?);
+        let cli = reqwest::Client::new();
+        let mut req = cli.get(&url).header(header::USER_AGENT, USER_AGENT);
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, token);
+        }
+        if let Some(entry) = cached {
+            if let Some(etag) = &entry.etag {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(lm) = &entry.last_modified {
+                req = req.header(header::IF_MODIFIED_SINCE, lm);
+            }
+        }
+
+        let resp = req.send().await?;
+        match resp.status() {
+            StatusCode::NOT_MODIFIED if cached.is_some() => return Ok(None),
+            StatusCode::UNAUTHORIZED => {
+                return Err(anyhow!(
+                    "{} requires authentication, add a token to cargo credentials",
+                    self.url
+                ));
+            }
+            StatusCode::FORBIDDEN if self.token.is_some() => {
+                return Err(anyhow!("{} rejected the token", self.url));
+            }
+            // crates.io answers 403 for missing files as well
+            StatusCode::NOT_FOUND | StatusCode::FORBIDDEN | StatusCode::GONE => {
+                return Err(anyhow!("crate {} not found in {}", name, self.url));
+            }
+            _ => {}
+        }
+        let resp = resp.error_for_status()?;
+        let get_header = |h| {
+            resp.headers()
+                .get(h)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let etag = get_header(header::ETAG);
+        let last_modified = get_header(header::LAST_MODIFIED);
+        let body = resp.text().await?;
+
+        Ok(Some(storage::CacheEntry::new(body, etag, last_modified)))
+    }
+}
+
+impl Registry for SparseRegistry {
+    fn name(&self) -> &str {
+        &self.url
+    }
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CratesDep>> {
+        Box::pin(async move {
+            let cached = self.cache.get(name)?;
+
+            let entry = match (self.cm, cached) {
+                (CacheMode::Offline, Some(entry)) => entry,
+                (CacheMode::Offline, None) => {
+                    return Err(anyhow!("{} is not cached, run without --offline", name));
+                }
+                (CacheMode::Ttl, Some(entry)) if entry.is_fresh(CACHE_TTL) => {
+                    info!("using cached {}", name);
+                    entry
+                }
+                (_, cached) => match self.download(name, cached.as_ref()).await {
+                    Ok(Some(entry)) => {
+                        self.cache.put(name, &entry)?;
+                        entry
+                    }
+                    Ok(None) => {
+                        info!("{} not modified", name);
+                        let mut entry = cached.ok_or(anyhow!("unexpected 304 for {}", name))?;
+                        entry.touch();
+                        self.cache.put(name, &entry)?;
+                        entry
+                    }
+                    Err(e) => match cached {
+                        Some(entry) => {
+                            warn!("fetching {} failed ({}), using stale cache", name, e);
+                            entry
+                        }
+                        None => return Err(e),
+                    },
+                },
+            };
+
+            parse_index_file(name, &entry.body)
+        })
+    }
+}
+
+/// Index files laid out like a sparse index in a local directory, or in its
+/// `index` subdirectory as `cargo local-registry` writes them.
+pub struct LocalRegistry {
+    path: PathBuf,
+    name: String,
+}
+
+impl LocalRegistry {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(anyhow!("registry directory {} not found", path.display()));
+        }
+        let index = path.join("index");
+        let path = if index.is_dir() {
+            index
+        } else {
+            path.to_path_buf()
+        };
+        Ok(Self {
+            name: path.display().to_string(),
+            path,
+        })
+    }
+    /// Working tree of a git index, bare repositories have no index files
+    /// to read.
+    pub fn from_git_checkout<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if path.join("HEAD").is_file() && path.join("objects").is_dir() {
+            return Err(anyhow!(
+                "git index {} is a bare repository, only checkouts are supported",
+                path.display()
+            ));
+        }
+        Self::new(path)
+    }
+    fn index_files(dir: &Path, res: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if entry.file_name() == ".git" || entry.file_name() == "config.json" {
+                continue;
+            }
+            if path.is_dir() {
+                Self::index_files(&path, res)?;
+            } else {
+                res.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Registry for LocalRegistry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CratesDep>> {
+        Box::pin(async move {
+            let path = self.path.join(index_path(name)?);
+            if !path.is_file() {
+                return Err(anyhow!("crate {} not found in {}", name, self.name));
+            }
+            parse_index_file(name, &fs::read_to_string(path)?)
+        })
+    }
+    fn search<'a>(&'a self, query: &'a str, page: usize) -> BoxFuture<'a, Result<SearchPage>> {
+        Box::pin(async move {
+            let mut files = Vec::new();
+            Self::index_files(&self.path, &mut files)?;
+
+            let mut fdeps = Vec::new();
+            for path in files {
+                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                if name.contains(&query.to_lowercase()) {
+                    fdeps.push(parse_index_file(name, &fs::read_to_string(&path)?)?);
+                }
+            }
+            Ok(search_page(fdeps, page))
+        })
+    }
+}
+
+/// Unpacked crates as written by `cargo vendor`, in `<name>` or
+/// `<name>-<version>` directories.
+pub struct DirectoryRegistry {
+    path: PathBuf,
+    name: String,
+}
+
+impl DirectoryRegistry {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(anyhow!("vendor directory {} not found", path.display()));
+        }
+        Ok(Self {
+            name: path.display().to_string(),
+            path: path.to_path_buf(),
+        })
+    }
+    /// Vendored versions of the crates whose directory name is accepted by
+    /// `f`, by crate name.
+    fn scan<F: Fn(&str) -> bool>(&self, f: F) -> Result<BTreeMap<String, CratesDep>> {
+        let mut res = BTreeMap::new();
+        for entry in fs::read_dir(&self.path)?.flatten() {
+            let manifest = entry.path().join("Cargo.toml");
+            let dir = entry.file_name().to_string_lossy().to_string();
+            if !f(&dir) || !manifest.is_file() {
+                continue;
+            }
+            let (name, iv) = match IndexVersion::from_manifest(&manifest) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("skipping vendored {}: {}", dir, e);
+                    continue;
+                }
+            };
+            res.entry(name.clone())
+                .or_insert(CratesDep {
+                    name,
+                    versions: HashMap::new(),
+                })
+                .versions
+                .insert(iv.vers.clone(), iv.to_crates_version());
+        }
+        Ok(res)
+    }
+}
+
+impl Registry for DirectoryRegistry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CratesDep>> {
+        Box::pin(async move {
+            let prefix = format!("{}-", name);
+            self.scan(|dir| dir == name || dir.starts_with(&prefix))?
+                .remove(name)
+                .ok_or(anyhow!("crate {} is not vendored in {}", name, self.name))
+        })
+    }
+    fn search<'a>(&'a self, query: &'a str, page: usize) -> BoxFuture<'a, Result<SearchPage>> {
+        Box::pin(async move {
+            let query = query.to_lowercase();
+            let fdeps = self
+                .scan(|dir| dir.to_lowercase().contains(&query))?
+                .into_values()
+                .filter(|fdep| fdep.name.to_lowercase().contains(&query))
+                .collect();
+            Ok(search_page(fdeps, page))
+        })
+    }
+    fn metadata<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CrateMeta>> {
+        Box::pin(async move {
+            let prefix = format!("{}-", name);
+            let mut newest: Option<(Version, toml::Table)> = None;
+            for entry in fs::read_dir(&self.path)?.flatten() {
+                let dir = entry.file_name().to_string_lossy().to_string();
+                let manifest = entry.path().join("Cargo.toml");
+                if !(dir == name || dir.starts_with(&prefix)) || !manifest.is_file() {
+                    continue;
+                }
+                let Ok(table) = fs::read_to_string(&manifest)?.parse::<toml::Table>() else {
+                    continue;
+                };
+                let package = table.get("package");
+                if package.and_then(|p| p.get("name")).and_then(|n| n.as_str()) != Some(name) {
+                    continue;
+                }
+                let Some(v) = package
+                    .and_then(|p| p.get("version"))
+                    .and_then(|v| v.as_str())
+                    .and_then(|v| Version::parse(v).ok())
+                else {
+                    continue;
+                };
+                if newest.as_ref().is_none_or(|(nv, _)| v > *nv) {
+                    newest = Some((v, table));
+                }
+            }
+            let (_, table) =
+                newest.ok_or(anyhow!("crate {} is not vendored in {}", name, self.name))?;
+            let get = |k: &str| {
+                table
+                    .get("package")
+                    .and_then(|p| p.get(k))
+                    .and_then(|v| v.as_str())
+                    .map(|v| v.trim().to_string())
+            };
+            Ok(CrateMeta {
+                description: get("description"),
+                license: get("license"),
+                repository: get("repository"),
+                homepage: get("homepage"),
+                documentation: get("documentation"),
+            })
+        })
+    }
+}
+
+/// Crates held in memory, e.g. loaded from a json fixture of the form
+/// `{"serde": [<index line>, ...]}`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRegistry {
+    crates: BTreeMap<String, CratesDep>,
+}
+
+impl MemoryRegistry {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_json(fs::read_to_string(path)?)
+    }
+    pub fn from_json<S: AsRef<str>>(json: S) -> Result<Self> {
+        let obj = serde_json::from_str::<HashMap<String, Vec<JValue>>>(json.as_ref())?;
+
+        let mut reg = Self::default();
+        for (name, lines) in obj {
+            let mut versions = HashMap::new();
+            for line in lines {
+                let iv = serde_json::from_value::<IndexVersion>(line)?;
+                versions.insert(iv.vers.clone(), iv.to_crates_version());
+            }
+            reg.insert(CratesDep { name, versions });
+        }
+        Ok(reg)
+    }
+    pub fn insert(&mut self, fdep: CratesDep) {
+        self.crates.insert(fdep.name.to_lowercase(), fdep);
+    }
+}
+
+impl Registry for MemoryRegistry {
+    fn name(&self) -> &str {
+        "fixture"
+    }
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<CratesDep>> {
+        Box::pin(async move {
+            self.crates
+                .get(&name.to_lowercase())
+                .cloned()
+                .ok_or(anyhow!("crate {} not found in fixture", name))
+        })
+    }
+    fn search<'a>(&'a self, query: &'a str, page: usize) -> BoxFuture<'a, Result<SearchPage>> {
+        Box::pin(async move {
+            let fdeps = self
+                .crates
+                .iter()
+                .filter(|(name, _)| name.contains(&query.to_lowercase()))
+                .map(|(_, fdep)| fdep.clone())
+                .collect();
+            Ok(search_page(fdeps, page))
+        })
+    }
+}
+
+/// Stands in for every registry name, alternate registries included.
+impl RegistryProvider for MemoryRegistry {
+    fn get(&self, _name: Option<&str>) -> Result<Box<dyn Registry>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+fn search_page(mut fdeps: Vec<CratesDep>, page: usize) -> SearchPage {
+    fdeps.sort_by(|a, b| a.name.cmp(&b.name));
+    SearchPage {
+        total: fdeps.len(),
+        crates: fdeps
+            .into_iter()
+            .skip(page.saturating_sub(1) * SEARCH_PER_PAGE)
+            .take(SEARCH_PER_PAGE)
+            .map(|fdep| SearchEntry {
+                max_version: fdep.get_last_version(false, None).unwrap_or_default(),
+                name: fdep.name,
+                ..Default::default()
+            })
+            .collect(),
+    }
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Path of the crate file inside an index, e.g. `se/rd/serde`.
+pub fn index_path<S: AsRef<str>>(name: S) -> Result<String> {
+    parse::check_crate_name(name.as_ref())?;
+    let name = name.as_ref().to_lowercase();
+    Ok(match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexDep {
+    name: String,
+    #[serde(default)]
+    optional: bool,
+}
+
+/// One line of an index file, only the fields depi uses.
+#[derive(Debug, Deserialize)]
+struct IndexVersion {
+    vers: String,
+    #[serde(default)]
+    deps: Vec<IndexDep>,
+    #[serde(default)]
+    features: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    features2: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    rust_version: Option<String>,
+}
+
+impl IndexVersion {
+    /// Crate name and the index line a vendored `Cargo.toml` stands for.
+    fn from_manifest(path: &Path) -> Result<(String, Self)> {
+        let table = fs::read_to_string(path)?.parse::<toml::Table>()?;
+        let get = |k: &str| {
+            table
+                .get("package")
+                .and_then(|p| p.get(k))
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .ok_or(anyhow!("no package.{} in {}", k, path.display()))
+        };
+        let name = get("name")?;
+        let vers = get("version")?;
+
+        let mut features = HashMap::new();
+        if let Some(toml::Value::Table(ft)) = table.get("features") {
+            for (f, en) in ft {
+                let en = en
+                    .as_array()
+                    .map(|en| {
+                        en.iter()
+                            .filter_map(|e| e.as_str().map(|e| e.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                features.insert(f.to_string(), en);
+            }
+        }
+
+        let mut tables = vec![&table];
+        if let Some(toml::Value::Table(targets)) = table.get("target") {
+            tables.extend(targets.values().filter_map(|t| t.as_table()));
+        }
+        let mut deps = Vec::new();
+        for t in tables {
+            for field in ["dependencies", "build-dependencies"] {
+                let Some(toml::Value::Table(dt)) = t.get(field) else {
+                    continue;
+                };
+                for (dname, attrs) in dt {
+                    deps.push(IndexDep {
+                        name: dname.to_string(),
+                        optional: attrs.get("optional").and_then(|o| o.as_bool()) == Some(true),
+                    });
+                }
+            }
+        }
+
+        Ok((
+            name,
+            Self {
+                vers,
+                deps,
+                features,
+                features2: HashMap::new(),
+                yanked: false,
+                rust_version: get("rust-version").ok(),
+            },
+        ))
+    }
+    fn to_crates_version(&self) -> CratesVersion {
+        CratesVersion {
+            features: self.feature_map(),
+            yanked: self.yanked,
+            rust_version: self.rust_version.clone(),
+        }
+    }
+    /// Explicit features plus the implicit ones cargo creates for optional
+    /// dependencies never referenced as `dep:name`.
+    fn feature_map(&self) -> BTreeMap<String, Vec<String>> {
+        let all = self.features.iter().chain(self.features2.iter());
+        let mut res = all
+            .clone()
+            .map(|(f, en)| (f.clone(), en.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let hidden = all
+            .flat_map(|(_, en)| en)
+            .filter_map(|e| e.strip_prefix("dep:"))
+            .collect::<Vec<_>>();
+        for d in self.deps.iter().filter(|d| d.optional) {
+            if !hidden.contains(&d.name.as_str()) && !res.contains_key(&d.name) {
+                res.insert(d.name.clone(), vec![format!("dep:{}", d.name)]);
+            }
+        }
+        res
+    }
+}
+
+fn parse_index_file(name: &str, body: &str) -> Result<CratesDep> {
+    let mut vhm = HashMap::new();
+
+    for line in body.lines().filter(|l| !l.trim().is_empty()) {
+        match serde_json::from_str::<IndexVersion>(line) {
+            Ok(iv) => {
+                vhm.insert(iv.vers.clone(), iv.to_crates_version());
+            }
+            Err(e) => warn!("skipping bad index line for {}: {}", name, e),
+        }
+    }
+
+    Ok(CratesDep {
+        name: name.to_string(),
+        versions: vhm,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::{process, thread};
+
+    use super::*;
+
+    const SERDE_V1: &str =
+        r#"{"name":"serde","vers":"1.0.0","deps":[],"features":{},"cksum":"","yanked":false}"#;
+    const SERDE_V2: &str = concat!(
+        r#"{"name":"serde","vers":"1.0.0","deps":[],"features":{},"cksum":"","yanked":false}"#,
+        "\n",
+        r#"{"name":"serde","vers":"1.0.1","deps":[],"features":{},"cksum":"","yanked":false}"#
+    );
+    const LAST_MODIFIED: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+
+    #[derive(Default)]
+    struct Served {
+        body: String,
+        etag: String,
+        /// Answer everything with 500.
+        broken: bool,
+        /// Request heads, lower-cased.
+        requests: Vec<String>,
+    }
+
+    /// Sparse index over HTTP serving one file, `se/rd/serde`, with an ETag
+    /// and Last-Modified, and 304 to a matching `If-None-Match`.
+    struct IndexServer {
+        url: String,
+        served: Arc<Mutex<Served>>,
+    }
+
+    impl IndexServer {
+        fn start(body: &str, etag: &str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let served = Arc::new(Mutex::new(Served {
+                body: body.to_string(),
+                etag: etag.to_string(),
+                ..Default::default()
+            }));
+
+            let state = served.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else {
+                        continue;
+                    };
+                    let mut head = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !head.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => head.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let head = String::from_utf8_lossy(&head).to_lowercase();
+
+                    let mut s = state.lock().unwrap();
+                    s.requests.push(head.clone());
+                    let (status, body) = if s.broken {
+                        ("500 Internal Server Error", String::new())
+                    } else if !head.starts_with("get /se/rd/serde ") {
+                        ("404 Not Found", String::new())
+                    } else if head.contains(&format!("if-none-match: {}", s.etag.to_lowercase())) {
+                        ("304 Not Modified", String::new())
+                    } else {
+                        ("200 OK", s.body.clone())
+                    };
+                    let resp = format!(
+                        "HTTP/1.1 {}\r\nETag: {}\r\nLast-Modified: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        s.etag,
+                        LAST_MODIFIED,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(resp.as_bytes());
+                }
+            });
+            Self { url, served }
+        }
+        fn requests(&self) -> Vec<String> {
+            self.served.lock().unwrap().requests.clone()
+        }
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("depi-cache-{}-{}", process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    fn registry(server: &IndexServer, cm: CacheMode, dir: &Path) -> SparseRegistry {
+        let cache = storage::CrateCache {
+            path: dir.to_path_buf(),
+        };
+        SparseRegistry::with_cache(&server.url, cm, cache)
+    }
+    fn versions(fd: &CratesDep) -> Vec<&str> {
+        fd.sorted_versions().into_iter().map(|(v, _)| v).collect()
+    }
+
+    #[tokio::test]
+    async fn sparse_fetch_caches_and_revalidates() {
+        let server = IndexServer::start(SERDE_V1, "\"v1\"");
+        let dir = cache_dir("revalidate");
+
+        // downloads once, then the fresh cache answers
+        let reg = registry(&server, CacheMode::Ttl, &dir);
+        assert_eq!(versions(&reg.fetch("serde").await.unwrap()), ["1.0.0"]);
+        assert_eq!(versions(&reg.fetch("serde").await.unwrap()), ["1.0.0"]);
+        assert_eq!(server.requests().len(), 1);
+
+        // refresh sends the validators and keeps the cache on 304
+        let reg = registry(&server, CacheMode::Refresh, &dir);
+        assert_eq!(versions(&reg.fetch("serde").await.unwrap()), ["1.0.0"]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+        assert!(requests[1].contains(&format!(
+            "if-modified-since: {}",
+            LAST_MODIFIED.to_lowercase()
+        )));
+
+        // a changed file replaces the cache
+        {
+            let mut s = server.served.lock().unwrap();
+            s.body = SERDE_V2.to_string();
+            s.etag = "\"v2\"".to_string();
+        }
+        assert_eq!(
+            versions(&reg.fetch("serde").await.unwrap()),
+            ["1.0.1", "1.0.0"]
+        );
+        let cached = storage::CrateCache { path: dir.clone() }
+            .get("serde")
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"v2\""));
+
+        // offline never asks
+        let reg = registry(&server, CacheMode::Offline, &dir);
+        assert_eq!(
+            versions(&reg.fetch("serde").await.unwrap()),
+            ["1.0.1", "1.0.0"]
+        );
+        assert!(reg.fetch("tokio").await.is_err());
+        assert_eq!(server.requests().len(), 3);
+
+        // a failing index falls back to the stale cache
+        server.served.lock().unwrap().broken = true;
+        let reg = registry(&server, CacheMode::Refresh, &dir);
+        assert_eq!(
+            versions(&reg.fetch("serde").await.unwrap()),
+            ["1.0.1", "1.0.0"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sparse_fetch_missing_crate() {
+        let server = IndexServer::start(SERDE_V1, "\"v1\"");
+        let dir = cache_dir("missing");
+        let reg = registry(&server, CacheMode::Ttl, &dir);
+
+        let err = reg.fetch("tokio").await.unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+        assert!(reg.fetch("").await.is_err());
+        assert_eq!(server.requests().len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn git_index_checkout() {
+        let dir = cache_dir("git-index");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("se/rd")).unwrap();
+        fs::write(dir.join("config.json"), r#"{"dl":"https://example.com"}"#).unwrap();
+        fs::write(dir.join("se/rd/serde"), SERDE_V2).unwrap();
+
+        let regs = |url: String| Registries {
+            config: CargoConfig {
+                registries: HashMap::from([(
+                    "my".to_string(),
+                    config::RegistryConfig {
+                        index: Some(url),
+                        token: None,
+                    },
+                )]),
+                ..Default::default()
+            },
+            cm: CacheMode::Offline,
+        };
+        for url in [
+            format!("file://{}", dir.display()),
+            format!("registry+file://{}", dir.display()),
+        ] {
+            let reg = regs(url).get(Some("my")).unwrap();
+            assert_eq!(
+                versions(&reg.fetch("serde").await.unwrap()),
+                ["1.0.1", "1.0.0"]
+            );
+            let found = reg.search("", 1).await.unwrap();
+            assert_eq!(found.total, 1);
+        }
+
+        let err = regs("https://example.com/index.git".to_string())
+            .get(Some("my"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("is a git index"), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        let err = regs(format!("file://{}", dir.display()))
+            .get(Some("my"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("bare repository"), "{}", err);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn vendored_directory() {
+        let dir = cache_dir("vendor");
+        let write = |rel: &str, content: &str| {
+            let path = dir.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write(
+            "serde/Cargo.toml",
+            r#"[package]
+name = "serde"
+version = "1.0.200"
+rust-version = "1.61"
+description = "A serialization framework"
+
+[features]
+default = ["std"]
+std = []
+
+[dependencies]
+serde_derive = { version = "1", optional = true }
+"#,
+        );
+        write(
+            "serde-1.0.100/Cargo.toml",
+            "[package]\nname = \"serde\"\nversion = \"1.0.100\"\ndescription = \"old\"\n",
+        );
+        write(
+            "serde_json/Cargo.toml",
+            "[package]\nname = \"serde_json\"\nversion = \"1.0.140\"\n",
+        );
+        write("serde-broken/Cargo.toml", "[package]\nname = \"serde\"\n");
+        write("serde-nothing/README.md", "no manifest");
+
+        let reg = DirectoryRegistry::new(&dir).unwrap();
+        let fd = reg.fetch("serde").await.unwrap();
+        assert_eq!(versions(&fd), ["1.0.200", "1.0.100"]);
+        let cv = &fd.versions["1.0.200"];
+        assert_eq!(cv.rust_version.as_deref(), Some("1.61"));
+        assert_eq!(
+            cv.features.keys().collect::<Vec<_>>(),
+            ["default", "serde_derive", "std"]
+        );
+        assert_eq!(
+            versions(&reg.fetch("serde_json").await.unwrap()),
+            ["1.0.140"]
+        );
+        let err = reg.fetch("tokio").await.unwrap_err();
+        assert!(err.to_string().contains("not vendored"), "{}", err);
+
+        assert_eq!(reg.search("serde", 1).await.unwrap().total, 2);
+        let meta = reg.metadata("serde").await.unwrap();
+        assert_eq!(
+            meta.description.as_deref(),
+            Some("A serialization framework")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(DirectoryRegistry::new(&dir).is_err());
+    }
+
+    #[test]
+    fn index_paths() {
+        for (name, path) in [
+            ("a", "1/a"),
+            ("ab", "2/ab"),
+            ("abc", "3/a/abc"),
+            ("serde", "se/rd/serde"),
+            ("Serde_JSON", "se/rd/serde_json"),
+        ] {
+            assert_eq!(index_path(name).unwrap(), path);
+        }
+        for name in ["", "ü", "añb", "1abc", "a b", "../x"] {
+            assert!(index_path(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn implicit_optional_features() {
+        let line = concat!(
+            r#"{"name":"serde","vers":"1.0.0","deps":["#,
+            r#"{"name":"serde_derive","optional":true},"#,
+            r#"{"name":"log","optional":true},"#,
+            r#"{"name":"itoa","optional":false}],"#,
+            r#""features":{"default":["derive"],"derive":["serde_derive"]},"#,
+            r#""features2":{"logging":["dep:log"]},"cksum":"","yanked":false}"#,
+        );
+        let cd = parse_index_file("serde", line).unwrap();
+        let cv = &cd.versions["1.0.0"];
+        // log is hidden behind `dep:log`, itoa is not optional
+        assert_eq!(
+            cv.features.keys().collect::<Vec<_>>(),
+            ["default", "derive", "logging", "serde_derive"]
+        );
+        assert_eq!(cv.features["serde_derive"], ["dep:serde_derive"]);
+
+        assert_eq!(
+            cv.feature_closure(&["default"])
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["default", "dep:serde_derive", "derive", "serde_derive"]
+        );
+        assert_eq!(cv.dropped_defaults::<&str>(&[]), ["derive", "serde_derive"]);
+        assert_eq!(
+            cv.redundant_features(&["serde_derive", "derive"]),
+            [("serde_derive".to_string(), "derive".to_string())]
+        );
+        assert_eq!(
+            cv.dropped_defaults(&["logging"]),
+            ["derive", "serde_derive"]
+        );
+    }
+}
@@ -1,6 +1,12 @@
-use std::{collections::HashMap, env, fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JValue;
 
 pub struct AliasStorage {
@@ -51,6 +57,7 @@ impl AliasStorage {
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn get<S: AsRef<str>>(&self, k: S) -> Option<&String> {
         self.aliases.get(k.as_ref())
     }
@@ -66,6 +73,104 @@ impl AliasStorage {
     }
 }
 
+/// Registry index files saved under `<storage>/cache/<index>`, one file
+/// per crate.
+pub struct CrateCache {
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Seconds since the unix epoch.
+    pub fetched_at: u64,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+impl CacheEntry {
+    pub fn new(body: String, etag: Option<String>, last_modified: Option<String>) -> Self {
+        Self {
+            fetched_at: now_secs(),
+            etag,
+            last_modified,
+            body,
+        }
+    }
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        now_secs().saturating_sub(self.fetched_at) < ttl.as_secs()
+    }
+    /// Marks the entry as just revalidated.
+    pub fn touch(&mut self) {
+        self.fetched_at = now_secs();
+    }
+}
+
+impl CrateCache {
+    fn init_if_no_exist(index: &str) -> Result<PathBuf> {
+        let dir = get_storage_directory_by_os()?
+            .join("cache")
+            .join(cache_dir_name(index));
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        Ok(dir)
+    }
+    /// Cache of the index at `index` url.
+    pub fn load<S: AsRef<str>>(index: S) -> Result<Self> {
+        let path = Self::init_if_no_exist(index.as_ref())?;
+        Ok(Self { path })
+    }
+    fn entry_path<S: AsRef<str>>(&self, name: S) -> PathBuf {
+        self.path
+            .join(format!("{}.json", name.as_ref().to_lowercase()))
+    }
+
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Result<Option<CacheEntry>> {
+        let path = self.entry_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        match serde_json::from_str::<CacheEntry>(&content) {
+            Ok(entry) => Ok(Some(entry)),
+            Err(e) => {
+                log::warn!("ignoring corrupted cache entry {}: {}", path.display(), e);
+                Ok(None)
+            }
+        }
+    }
+    pub fn put<S: AsRef<str>>(&self, name: S, entry: &CacheEntry) -> Result<()> {
+        fs::write(self.entry_path(name), serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+}
+
+/// `https://index.crates.io/` -> `index.crates.io`,
+/// `http://127.0.0.1:8080/idx/` -> `127.0.0.1_8080_idx`.
+fn cache_dir_name(index: &str) -> String {
+    let url = index.split_once("://").map_or(index, |(_, rest)| rest);
+    url.trim_end_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
 fn get_storage_directory_by_os() -> Result<PathBuf> {
     match env::consts::OS {
         "linux" => {
@@ -28,6 +28,7 @@ impl<S: AsRef<str>> From<S> for ColorType {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Default, Clone, Copy, Debug)]
 pub enum DColor {
     #[default]
@@ -63,6 +64,24 @@ pub mod funcs {
     use anyhow::{Result, anyhow};
     use std::{env, fs, path::Path};
 
+    /// Asks for a number from 1 to `max`, `None` for an empty answer.
+    pub fn read_choice<S: AsRef<str>>(prompt: S, max: usize) -> Result<Option<usize>> {
+        use std::io::{self, Write};
+
+        print!("{} [1-{}]: ", prompt.as_ref(), max);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        match line.parse::<usize>() {
+            Ok(n) if (1..=max).contains(&n) => Ok(Some(n)),
+            _ => Err(anyhow!("invalid choice {}", line)),
+        }
+    }
+
     pub fn current_absolute() -> Result<String> {
         absolutize(env::current_dir().unwrap_or(".".into()))
     }
@@ -121,6 +140,19 @@ pub mod style {
         println!("{}", name.as_ref().to_ascii_uppercase().bold().on_cyan());
         println!("{}", "=".repeat(TERMINAL_SIZE.0 as usize).cyan());
     }
+    pub fn print_start_msg_for<S: AsRef<str>>(name: S, member: Option<&str>) {
+        match member {
+            Some(m) => {
+                println!(
+                    "{} {}",
+                    name.as_ref().to_ascii_uppercase().bold().on_cyan(),
+                    m.bold().cyan()
+                );
+                println!("{}", "=".repeat(TERMINAL_SIZE.0 as usize).cyan());
+            }
+            None => print_start_msg(name),
+        }
+    }
     pub fn print_end_msg() {
         println!("{}", "=".repeat(TERMINAL_SIZE.0 as usize).cyan());
     }
@@ -134,6 +166,33 @@ pub mod style {
     pub fn print_cargo_field_a(dtype: &DType) {
         println!("{}", dtype.to_cargo_field().red())
     }
+    pub fn print_breaking_label(tabbing: usize) {
+        println!("{}{}", " ".repeat(tabbing), "breaking:".red().bold())
+    }
+    pub fn print_yanked_label(tabbing: usize) {
+        println!(
+            "{}{} {}",
+            " ".repeat(tabbing),
+            "yanked:".red().bold(),
+            "(no replacement)".dimmed()
+        )
+    }
+    pub fn print_held_back_label<S: AsRef<str>>(tabbing: usize, msrv: S) {
+        println!(
+            "{}{} {}",
+            " ".repeat(tabbing),
+            "held back:".yellow().bold(),
+            format!("(rust-version {})", msrv.as_ref()).dimmed()
+        )
+    }
+    pub fn print_downgrade_label(tabbing: usize) {
+        println!(
+            "{}{} {}",
+            " ".repeat(tabbing),
+            "downgrade:".yellow().bold(),
+            "(not applied)".dimmed()
+        )
+    }
     pub fn print_colored_ref_dep_version_update<S: AsRef<str>>(
         dep: &Dep,
         oldv: S,
@@ -141,12 +200,14 @@ pub mod style {
         mvl: usize,
         tabbing: usize,
         dct: DColor,
+        warn: Option<&str>,
     ) {
         let dname = dep.name.as_ref();
         let dver = dep.version.as_ref();
         let oldv = oldv.as_ref();
-        print_colored_val_dep_version_update(dname, dver, oldv, mnl, mvl, tabbing, dct);
+        print_colored_val_dep_version_update(dname, dver, oldv, mnl, mvl, tabbing, dct, warn);
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn print_colored_val_dep_version_update<S: AsRef<str>>(
         dname: S,
         dver: S,
@@ -155,13 +216,14 @@ pub mod style {
         mvl: usize,
         tabbing: usize,
         dct: DColor,
+        warn: Option<&str>,
     ) {
         let dname = dname.as_ref();
         let dver = dver.as_ref();
         let oldv = oldv.as_ref();
         match dct {
             DColor::WithoutColor => {
-                println!(
+                print!(
                     "{}{:<mnl$} {:<mvl$} {} {}",
                     " ".repeat(tabbing),
                     dname.bold(),
@@ -171,7 +233,7 @@ pub mod style {
                 )
             }
             DColor::GOIDA => {
-                println!(
+                print!(
                     "{}{:<mnl$} {:<mvl$} {} {}",
                     " ".repeat(tabbing),
                     dname.bold(),
@@ -181,7 +243,7 @@ pub mod style {
                 )
             }
             DColor::Osetia => {
-                println!(
+                print!(
                     "{}{:<mnl$} {:<mvl$} {} {}",
                     " ".repeat(tabbing),
                     dname.bold(),
@@ -191,12 +253,10 @@ pub mod style {
                 )
             }
             DColor::Poland => {
-                let oll = oldv.len() / 2;
-                let oldvl = &oldv[0..oll];
-                let oldvr = &oldv[oll..oldv.len()];
+                let (oldvl, oldvr) = split_half(oldv);
 
                 let nmvl = mvl - oldvl.len();
-                println!(
+                print!(
                     "{}{:<mnl$} {}{:<nmvl$} {} {}",
                     " ".repeat(tabbing),
                     dname.bold(),
@@ -207,6 +267,7 @@ pub mod style {
                 )
             }
         }
+        print_warn_column(warn);
     }
     pub fn print_colored_ref_dep_full(
         dep: &Dep,
@@ -214,12 +275,14 @@ pub mod style {
         mvl: usize,
         tabbing: usize,
         dct: DColor,
+        warn: Option<&str>,
     ) {
         let dname = &dep.name;
-        let dver = &dep.version;
+        let dver = &dep.version_label();
         let dfeat = dep.features.as_deref();
-        print_colored_val_dep_full(dname, dver, dfeat, mnl, mvl, tabbing, dct);
+        print_colored_val_dep_full(dname, dver, dfeat, mnl, mvl, tabbing, dct, warn);
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn print_colored_val_dep_full<S: AsRef<str>>(
         dname: S,
         dver: S,
@@ -228,13 +291,14 @@ pub mod style {
         mvl: usize,
         tabbing: usize,
         dct: DColor,
+        warn: Option<&str>,
     ) {
         let dname = dname.as_ref();
         let dver = dver.as_ref();
         match dct {
             DColor::WithoutColor => {
                 if let Some(fs) = &dfeat {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$} {} {}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -244,7 +308,7 @@ pub mod style {
                         fs.join(", "),
                     );
                 } else {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$}",
                         " ".repeat(tabbing),
                         &dname,
@@ -255,7 +319,7 @@ pub mod style {
             }
             DColor::GOIDA => {
                 if let Some(fs) = &dfeat {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$} {} {}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -265,7 +329,7 @@ pub mod style {
                         fs.join(", ").red(),
                     );
                 } else {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -276,7 +340,7 @@ pub mod style {
             }
             DColor::Osetia => {
                 if let Some(fs) = &dfeat {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$} {} {}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -286,7 +350,7 @@ pub mod style {
                         fs.join(", ").red(),
                     );
                 } else {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -297,13 +361,10 @@ pub mod style {
             }
             DColor::Poland => {
                 if let Some(fs) = &dfeat {
-                    let dvr = &dver;
-                    let dvh = dvr.len() / 2;
-                    let dvrl = &dvr[0..dvh];
-                    let dvrr = &dvr[dvh..dvr.len()];
+                    let (dvrl, dvrr) = split_half(dver);
 
                     let nmvl = mvl - dvrl.len();
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {}{:<nmvl$} {} {}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -314,7 +375,7 @@ pub mod style {
                         fs.join(", ").red(),
                     );
                 } else {
-                    println!(
+                    print!(
                         "{}{:<mnl$} {} {:<mvl$}",
                         " ".repeat(tabbing),
                         &dname.bold(),
@@ -324,53 +385,921 @@ pub mod style {
                 }
             }
         }
+        print_warn_column(warn);
+    }
+    /// Search result: numbered `name @ version` line in the dependency
+    /// columns, then downloads and description below.
+    #[allow(clippy::too_many_arguments)]
+    pub fn print_colored_search_entry<S: AsRef<str>>(
+        n: usize,
+        name: S,
+        ver: S,
+        downloads: Option<u64>,
+        desc: Option<&str>,
+        mnl: usize,
+        mvl: usize,
+        tabbing: usize,
+        dct: DColor,
+    ) {
+        print!("{}{} ", " ".repeat(tabbing), format!("{:>2}.", n).dimmed());
+        print_colored_val_dep_full(name, ver, None, mnl, mvl, 0, dct, None);
+
+        let tabbing = tabbing + 4;
+        let mut parts = Vec::new();
+        if let Some(downloads) = downloads {
+            parts.push(format!("{} downloads", human_count(downloads)));
+        }
+        if let Some(desc) = desc.filter(|d| !d.is_empty()) {
+            parts.push(desc.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        if parts.is_empty() {
+            return;
+        }
+        let mut info = parts.join(" | ");
+        let width = (TERMINAL_SIZE.0 as usize).saturating_sub(tabbing).max(20);
+        if info.chars().count() > width {
+            info = format!("{}...", info.chars().take(width - 3).collect::<String>());
+        }
+        println!("{}{}", " ".repeat(tabbing), info.dimmed());
+    }
+    pub fn print_search_footer(page: usize, pages: usize, total: usize) {
+        if total == 0 {
+            println!("{}", "nothing found".dimmed());
+            return;
+        }
+        println!(
+            "{} {}/{} {}",
+            "page".dimmed(),
+            page.to_string().bold(),
+            pages,
+            format!("({} crates)", total).dimmed()
+        );
+    }
+    pub fn print_description<S: AsRef<str>>(desc: S) {
+        println!("{}", desc.as_ref().dimmed())
+    }
+    pub fn print_info_section<S: AsRef<str>>(name: S) {
+        println!("{}", name.as_ref().green())
+    }
+    pub fn print_info_field<S: AsRef<str>>(key: S, val: S) {
+        println!(
+            "{} {}",
+            format!("{:<13}", format!("{}:", key.as_ref())).dimmed(),
+            val.as_ref()
+        );
+    }
+    /// Feature with what it enables, after a one character mark, e.g. `*`
+    /// for the ones in `default`. `implied` is what it turns on only
+    /// through other features.
+    #[allow(clippy::too_many_arguments)]
+    pub fn print_colored_feature<S: AsRef<str>>(
+        name: S,
+        enables: &[String],
+        implied: &[String],
+        mark: char,
+        mnl: usize,
+        tabbing: usize,
+        dct: DColor,
+    ) {
+        let name = name.as_ref();
+        let mark = mark.to_string().bold();
+        let enables = if enables.is_empty() {
+            "-".dimmed()
+        } else {
+            match dct {
+                DColor::WithoutColor => enables.join(", ").normal(),
+                DColor::GOIDA => enables.join(", ").blue(),
+                DColor::Osetia => enables.join(", ").yellow(),
+                DColor::Poland => enables.join(", ").red(),
+            }
+        };
+        print!(
+            "{}{} {:<mnl$} {} {}",
+            " ".repeat(tabbing),
+            mark,
+            name.bold(),
+            "->".dimmed(),
+            enables
+        );
+        match implied.is_empty() {
+            true => println!(),
+            false => println!(" {}", format!("[+ {}]", implied.join(", ")).dimmed()),
+        }
+    }
+    /// Column titles of `depi outdated`, `widths` is name, requirement,
+    /// locked, compatible and latest.
+    pub fn print_outdated_header(widths: &[usize; 5], tabbing: usize) {
+        let [nw, rw, lw, cw, _] = *widths;
+        println!(
+            "{}{}",
+            " ".repeat(tabbing),
+            format!(
+                "{:<nw$} {:<rw$} {:<lw$} {:<cw$} {}",
+                "name", "requirement", "locked", "compatible", "latest"
+            )
+            .dimmed()
+        );
+    }
+    /// Row of `depi outdated`. The compatible version stands out when the
+    /// lock is behind it, the latest when the requirement doesn't allow it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn print_colored_outdated<S: AsRef<str>>(
+        name: S,
+        cols: &[S; 4],
+        widths: &[usize; 5],
+        lock_behind: bool,
+        req_behind: bool,
+        tabbing: usize,
+        dct: DColor,
+    ) {
+        let [nw, rw, lw, cw, _] = *widths;
+        let [req, locked, compatible, latest] = cols.each_ref().map(|c| c.as_ref());
+        let compatible = match (lock_behind, dct) {
+            (false, _) | (true, DColor::WithoutColor) => compatible.normal(),
+            (true, DColor::GOIDA) => compatible.blue(),
+            (true, DColor::Osetia) => compatible.yellow(),
+            (true, DColor::Poland) => compatible.white().bold(),
+        };
+        let latest = match (req_behind, dct) {
+            (false, _) => latest.normal(),
+            (true, DColor::WithoutColor) => latest.bold(),
+            (true, _) => latest.red().bold(),
+        };
+        println!(
+            "{}{:<nw$} {:<rw$} {:<lw$} {:<cw$} {}",
+            " ".repeat(tabbing),
+            name.as_ref().bold(),
+            req,
+            locked.dimmed(),
+            compatible,
+            latest
+        );
+    }
+    pub fn print_warning<S: AsRef<str>>(tabbing: usize, msg: S) {
+        println!(
+            "{}{} {}",
+            " ".repeat(tabbing),
+            "warning:".yellow().bold(),
+            msg.as_ref()
+        )
+    }
+    /// `1234567` as `1.2M`.
+    fn human_count(n: u64) -> String {
+        match n {
+            0..1_000 => n.to_string(),
+            1_000..1_000_000 => format!("{:.1}K", n as f64 / 1e3),
+            1_000_000..1_000_000_000 => format!("{:.1}M", n as f64 / 1e6),
+            _ => format!("{:.1}B", n as f64 / 1e9),
+        }
+    }
+    /// Ends the dependency line, with the warning in the last column.
+    fn print_warn_column(warn: Option<&str>) {
+        match warn {
+            Some(w) => println!(" {}", w.red().bold()),
+            None => println!(),
+        }
+    }
+    /// Halves of the string for the two-colored versions, split between
+    /// characters since path and git labels need not be ASCII.
+    fn split_half(s: &str) -> (&str, &str) {
+        let half = s.chars().count() / 2;
+        let at = s.char_indices().nth(half).map_or(s.len(), |(i, _)| i);
+        s.split_at(at)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn split_half_keeps_characters() {
+            for (s, halves) in [
+                ("", ("", "")),
+                ("1.0", ("1", ".0")),
+                ("1.0.200", ("1.0", ".200")),
+                ("path:üabcde", ("path:", "üabcde")),
+                ("üü", ("ü", "ü")),
+                ("ü", ("", "ü")),
+            ] {
+                assert_eq!(split_half(s), halves, "{:?}", s);
+            }
+        }
     }
 }
 
 pub mod ver {
+    use std::{cmp::Ordering, fmt, str::FromStr};
+
     use anyhow::{Result, anyhow};
-    use log::warn;
 
-    #[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Debug, Default)]
-    pub struct OrdVersion(pub u32, pub u32, pub u32);
+    /// Dot-separated part of a pre-release.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum Identifier {
+        Numeric(u64),
+        AlphaNumeric(String),
+    }
+
+    impl Identifier {
+        fn parse(s: &str) -> Result<Self> {
+            check_ident(s)?;
+            if s.chars().all(|c| c.is_ascii_digit()) {
+                if s.len() > 1 && s.starts_with('0') {
+                    return Err(anyhow!("leading zero in pre-release identifier {}", s));
+                }
+                Ok(Self::Numeric(s.parse()?))
+            } else {
+                Ok(Self::AlphaNumeric(s.to_string()))
+            }
+        }
+    }
+
+    impl Ord for Identifier {
+        fn cmp(&self, other: &Self) -> Ordering {
+            match (self, other) {
+                (Self::Numeric(a), Self::Numeric(b)) => a.cmp(b),
+                (Self::AlphaNumeric(a), Self::AlphaNumeric(b)) => a.cmp(b),
+                (Self::Numeric(_), Self::AlphaNumeric(_)) => Ordering::Less,
+                (Self::AlphaNumeric(_), Self::Numeric(_)) => Ordering::Greater,
+            }
+        }
+    }
+    impl PartialOrd for Identifier {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl fmt::Display for Identifier {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Numeric(n) => write!(f, "{}", n),
+                Self::AlphaNumeric(s) => write!(f, "{}", s),
+            }
+        }
+    }
+
+    /// SemVer 2.0 version: `MAJOR.MINOR.PATCH[-PRE][+BUILD]`.
+    ///
+    /// Ordering follows SemVer precedence, build metadata is only used as a
+    /// last tie-breaker so that `Ord` stays consistent with `Eq`.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+    pub struct Version {
+        pub major: u64,
+        pub minor: u64,
+        pub patch: u64,
+        pub pre: Vec<Identifier>,
+        pub build: Vec<String>,
+    }
 
-    impl OrdVersion {
+    impl Version {
         pub fn parse<S: AsRef<str>>(s: S) -> Result<Self> {
-            let mut res = Self::default();
+            let s = s.as_ref().trim();
+
+            let (s, build) = match s.split_once('+') {
+                Some((s, build)) => {
+                    let build = build.split('.').map(|b| b.to_string()).collect::<Vec<_>>();
+                    for b in &build {
+                        check_ident(b)?;
+                    }
+                    (s, build)
+                }
+                None => (s, Vec::new()),
+            };
+            let (s, pre) = match s.split_once('-') {
+                Some((s, pre)) => (
+                    s,
+                    pre.split('.')
+                        .map(Identifier::parse)
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                None => (s, Vec::new()),
+            };
+
+            let core = s.split('.').collect::<Vec<_>>();
+            if core.len() != 3 {
+                return Err(anyhow!("invalid version {}: expected 3 parts", s));
+            }
+            let mut nums = [0; 3];
+            for (i, part) in core.iter().enumerate() {
+                if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(anyhow!("invalid version part {:?}", part));
+                }
+                if part.len() > 1 && part.starts_with('0') {
+                    return Err(anyhow!("leading zero in version part {}", part));
+                }
+                nums[i] = part.parse()?;
+            }
+
+            Ok(Self {
+                major: nums[0],
+                minor: nums[1],
+                patch: nums[2],
+                pre,
+                build,
+            })
+        }
+        /// `rust-version` value like `1.74` or `1.74.1`, the missing patch
+        /// is zero.
+        pub fn parse_rust_version<S: AsRef<str>>(s: S) -> Result<Self> {
+            let s = s.as_ref().trim();
+            match s.split('.').count() {
+                2 => Self::parse(format!("{}.0", s)),
+                3 => Self::parse(s),
+                _ => Err(anyhow!("invalid rust-version {}", s)),
+            }
+        }
+        pub fn is_prerelease(&self) -> bool {
+            !self.pre.is_empty()
+        }
+        /// Whether both versions are in the same caret range, i.e. going from
+        /// one to the other is not a breaking change.
+        pub fn is_caret_compatible(&self, other: &Self) -> bool {
+            let key = |v: &Self| match (v.major, v.minor) {
+                (0, 0) => (0, 0, v.patch),
+                (0, minor) => (0, minor, 0),
+                (major, _) => (major, 0, 0),
+            };
+            key(self) == key(other)
+        }
+        /// SemVer precedence, ignores build metadata.
+        pub fn cmp_precedence(&self, other: &Self) -> Ordering {
+            (self.major, self.minor, self.patch)
+                .cmp(&(other.major, other.minor, other.patch))
+                .then_with(|| cmp_pre(&self.pre, &other.pre))
+        }
+    }
+
+    impl Ord for Version {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.cmp_precedence(other)
+                .then_with(|| self.build.cmp(&other.build))
+        }
+    }
+    impl PartialOrd for Version {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl FromStr for Version {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self> {
+            Self::parse(s)
+        }
+    }
+    impl fmt::Display for Version {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+            if !self.pre.is_empty() {
+                let pre = self.pre.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                write!(f, "-{}", pre.join("."))?;
+            }
+            if !self.build.is_empty() {
+                write!(f, "+{}", self.build.join("."))?;
+            }
+            Ok(())
+        }
+    }
 
-            let mut s = s.as_ref();
+    /// Pre-release precedence where "no pre-release" is the greatest.
+    fn cmp_pre(a: &[Identifier], b: &[Identifier]) -> Ordering {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.cmp(b),
+        }
+    }
 
-            if let Some((left, right)) = s.split_once("-") {
-                s = left;
-                warn!("version with suffix {right} is not supported");
-                warn!("current version is {left}");
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Op {
+        /// No operator, same as `^`.
+        Bare,
+        Caret,
+        Tilde,
+        Exact,
+        Greater,
+        GreaterEq,
+        Less,
+        LessEq,
+        Wildcard,
+    }
+
+    impl Op {
+        fn as_str(&self) -> &'static str {
+            match self {
+                Op::Bare | Op::Wildcard => "",
+                Op::Caret => "^",
+                Op::Tilde => "~",
+                Op::Exact => "=",
+                Op::Greater => ">",
+                Op::GreaterEq => ">=",
+                Op::Less => "<",
+                Op::LessEq => "<=",
             }
-            let start = s.chars().nth(0).unwrap();
-            if !start.is_ascii_digit() {
-                s = s.trim_start_matches(start);
+        }
+    }
+
+    /// One comparator of a requirement, e.g. `>=1.2` or `~0.3.1-beta`.
+    /// Missing minor/patch mean the version was partial or a wildcard.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Comparator {
+        pub op: Op,
+        pub major: u64,
+        pub minor: Option<u64>,
+        pub patch: Option<u64>,
+        pub pre: Vec<Identifier>,
+    }
+
+    impl Comparator {
+        /// Parses one comparator, `None` means a bare `*` which matches
+        /// everything.
+        fn parse(s: &str) -> Result<Option<Self>> {
+            let s = s.trim();
+            let (op, rest) = [
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Exact),
+                ("~", Op::Tilde),
+                ("^", Op::Caret),
+            ]
+            .into_iter()
+            .find_map(|(p, op)| s.strip_prefix(p).map(|rest| (op, rest.trim_start())))
+            .unwrap_or((Op::Bare, s));
+
+            if rest.is_empty() {
+                return Err(anyhow!("empty version requirement {:?}", s));
             }
 
-            let s = s.split(".").collect::<Vec<_>>();
+            // build metadata is meaningless in requirements
+            let rest = rest.split_once('+').map(|(r, _)| r).unwrap_or(rest);
+            let (core, pre) = match rest.split_once('-') {
+                Some((core, pre)) => (
+                    core,
+                    pre.split('.')
+                        .map(Identifier::parse)
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                None => (rest, Vec::new()),
+            };
+            if core.split('.').count() > 3 {
+                return Err(anyhow!("too many version parts in {}", s));
+            }
 
-            match s.len() {
-                1 => res.0 = s[0].parse()?,
-                2 => {
-                    res.0 = s[0].parse()?;
-                    res.1 = s[1].parse()?;
+            let mut parts = Vec::new();
+            let mut wildcard = false;
+            for part in core.split('.') {
+                match part {
+                    "*" | "x" | "X" => wildcard = true,
+                    _ if wildcard => {
+                        return Err(anyhow!("unexpected version after wildcard in {}", s));
+                    }
+                    _ if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) => {
+                        return Err(anyhow!("invalid version part {:?} in {}", part, s));
+                    }
+                    _ => parts.push(part.parse::<u64>()?),
                 }
-                3 => {
-                    res.0 = s[0].parse()?;
-                    res.1 = s[1].parse()?;
-                    res.2 = s[2].parse()?;
+            }
+            let op = if wildcard {
+                if !matches!(op, Op::Bare | Op::Exact) {
+                    return Err(anyhow!("unexpected wildcard after operator in {}", s));
+                }
+                if parts.is_empty() {
+                    return Ok(None);
                 }
-                _ => return Err(anyhow!("invalid parts {}", s.len())),
+                Op::Wildcard
+            } else {
+                op
+            };
+            if !pre.is_empty() && parts.len() != 3 {
+                return Err(anyhow!("pre-release requires a full version in {}", s));
+            }
+
+            Ok(Some(Self {
+                op,
+                major: parts[0],
+                minor: parts.get(1).copied(),
+                patch: parts.get(2).copied(),
+                pre,
+            }))
+        }
+        pub fn matches(&self, v: &Version) -> bool {
+            match self.op {
+                Op::Exact | Op::Wildcard => self.matches_exact(v),
+                Op::Greater => self.matches_greater(v),
+                Op::GreaterEq => self.matches_exact(v) || self.matches_greater(v),
+                Op::Less => self.matches_less(v),
+                Op::LessEq => self.matches_exact(v) || self.matches_less(v),
+                Op::Tilde => self.matches_tilde(v),
+                Op::Bare | Op::Caret => self.matches_caret(v),
             }
-            Ok(res)
         }
+        fn matches_exact(&self, v: &Version) -> bool {
+            v.major == self.major
+                && self.minor.is_none_or(|m| v.minor == m)
+                && self.patch.is_none_or(|p| v.patch == p)
+                && v.pre == self.pre
+        }
+        fn matches_greater(&self, v: &Version) -> bool {
+            if v.major != self.major {
+                return v.major > self.major;
+            }
+            match self.minor {
+                None => return false,
+                Some(m) if v.minor != m => return v.minor > m,
+                _ => {}
+            }
+            match self.patch {
+                None => return false,
+                Some(p) if v.patch != p => return v.patch > p,
+                _ => {}
+            }
+            cmp_pre(&v.pre, &self.pre) == Ordering::Greater
+        }
+        fn matches_less(&self, v: &Version) -> bool {
+            if v.major != self.major {
+                return v.major < self.major;
+            }
+            match self.minor {
+                None => return false,
+                Some(m) if v.minor != m => return v.minor < m,
+                _ => {}
+            }
+            match self.patch {
+                None => return false,
+                Some(p) if v.patch != p => return v.patch < p,
+                _ => {}
+            }
+            cmp_pre(&v.pre, &self.pre) == Ordering::Less
+        }
+        fn matches_tilde(&self, v: &Version) -> bool {
+            if v.major != self.major || self.minor.is_some_and(|m| v.minor != m) {
+                return false;
+            }
+            match self.patch {
+                Some(p) if v.patch != p => v.patch > p,
+                _ => cmp_pre(&v.pre, &self.pre) != Ordering::Less,
+            }
+        }
+        fn matches_caret(&self, v: &Version) -> bool {
+            if v.major != self.major {
+                return false;
+            }
+            let Some(minor) = self.minor else {
+                return true;
+            };
+            let Some(patch) = self.patch else {
+                return if self.major > 0 {
+                    v.minor >= minor
+                } else {
+                    v.minor == minor
+                };
+            };
+
+            if self.major > 0 {
+                if v.minor != minor {
+                    return v.minor > minor;
+                } else if v.patch != patch {
+                    return v.patch > patch;
+                }
+            } else if minor > 0 {
+                if v.minor != minor {
+                    return false;
+                } else if v.patch != patch {
+                    return v.patch > patch;
+                }
+            } else if v.minor != minor || v.patch != patch {
+                return false;
+            }
+            cmp_pre(&v.pre, &self.pre) != Ordering::Less
+        }
+        /// A pre-release version is only allowed by a comparator which
+        /// itself names a pre-release of the same `major.minor.patch`.
+        fn allows_pre_of(&self, v: &Version) -> bool {
+            !self.pre.is_empty()
+                && self.major == v.major
+                && self.minor == Some(v.minor)
+                && self.patch == Some(v.patch)
+        }
+    }
+
+    impl fmt::Display for Comparator {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}{}", self.op.as_str(), self.major)?;
+            match (self.minor, self.patch) {
+                (Some(m), Some(p)) => write!(f, ".{}.{}", m, p)?,
+                (Some(m), None) if self.op == Op::Wildcard => write!(f, ".{}.*", m)?,
+                (Some(m), None) => write!(f, ".{}", m)?,
+                (None, _) if self.op == Op::Wildcard => write!(f, ".*")?,
+                (None, _) => {}
+            }
+            if !self.pre.is_empty() {
+                let pre = self.pre.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                write!(f, "-{}", pre.join("."))?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Cargo version requirement: comma-joined comparators, all of which
+    /// have to match. No comparators means `*`.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct VersionReq {
+        pub comparators: Vec<Comparator>,
     }
-    impl ToString for OrdVersion {
-        fn to_string(&self) -> String {
-            format!("{}.{}.{}", self.0, self.1, self.2)
+
+    impl VersionReq {
+        pub fn parse<S: AsRef<str>>(s: S) -> Result<Self> {
+            let s = s.as_ref().trim();
+            if s.is_empty() {
+                return Err(anyhow!("empty version requirement"));
+            }
+            let mut comparators = Vec::new();
+            for c in s.split(',') {
+                if let Some(c) = Comparator::parse(c)? {
+                    comparators.push(c);
+                }
+            }
+            Ok(Self { comparators })
+        }
+        pub fn matches(&self, v: &Version) -> bool {
+            self.comparators.iter().all(|c| c.matches(v))
+                && (v.pre.is_empty() || self.comparators.iter().any(|c| c.allows_pre_of(v)))
+        }
+        /// Smallest version the requirement allows, if it is bounded from
+        /// below.
+        pub fn lower_bound(&self) -> Option<Version> {
+            self.comparators
+                .iter()
+                .filter(|c| !matches!(c.op, Op::Less | Op::LessEq))
+                .map(|c| Version {
+                    major: c.major,
+                    minor: c.minor.unwrap_or(0),
+                    patch: c.patch.unwrap_or(0),
+                    pre: c.pre.clone(),
+                    build: Vec::new(),
+                })
+                .max()
+        }
+        /// Same kind of requirement moved to `v`, if it is a single
+        /// comparator which can be moved at all (`1.2`, `^1.2`, `~1.2`,
+        /// `=1.2.3`).
+        pub fn bump(&self, v: &Version) -> Option<Self> {
+            match self.comparators.as_slice() {
+                [c] if matches!(c.op, Op::Bare | Op::Caret | Op::Tilde | Op::Exact) => Some(Self {
+                    comparators: vec![Comparator {
+                        op: c.op,
+                        major: v.major,
+                        minor: Some(v.minor),
+                        patch: Some(v.patch),
+                        pre: v.pre.clone(),
+                    }],
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    impl FromStr for VersionReq {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self> {
+            Self::parse(s)
+        }
+    }
+    impl fmt::Display for VersionReq {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.comparators.is_empty() {
+                return write!(f, "*");
+            }
+            let cs = self
+                .comparators
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>();
+            write!(f, "{}", cs.join(", "))
+        }
+    }
+
+    fn check_ident(s: &str) -> Result<()> {
+        if s.is_empty() {
+            return Err(anyhow!("empty version identifier"));
+        }
+        if let Some(c) = s
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '-'))
+        {
+            return Err(anyhow!("invalid character {:?} in identifier {}", c, s));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn v(s: &str) -> Version {
+            Version::parse(s).unwrap()
+        }
+
+        #[test]
+        fn version_precedence() {
+            // the ordering example of the SemVer spec
+            let ordered = [
+                "1.0.0-alpha",
+                "1.0.0-alpha.1",
+                "1.0.0-alpha.beta",
+                "1.0.0-beta",
+                "1.0.0-beta.2",
+                "1.0.0-beta.11",
+                "1.0.0-rc.1",
+                "1.0.0",
+                "1.0.1",
+                "1.1.0",
+                "2.0.0",
+            ];
+            for w in ordered.windows(2) {
+                assert!(v(w[0]) < v(w[1]), "{} < {}", w[0], w[1]);
+            }
+            assert_eq!(v("1.0.0+a").cmp_precedence(&v("1.0.0+b")), Ordering::Equal);
+            assert_eq!(v("1.2.3-rc.1+build.5").to_string(), "1.2.3-rc.1+build.5");
+        }
+
+        #[test]
+        fn invalid_versions() {
+            for s in [
+                "",
+                "1",
+                "1.2",
+                "1.2.3.4",
+                "01.2.3",
+                "1.2.3-",
+                "1.2.3-01",
+                "1.2.3-a..b",
+                "1.x.3",
+            ] {
+                assert!(Version::parse(s).is_err(), "{:?}", s);
+            }
+            assert_eq!(Version::parse_rust_version("1.74").unwrap(), v("1.74.0"));
+            assert_eq!(Version::parse_rust_version("1.74.1").unwrap(), v("1.74.1"));
+            assert!(Version::parse_rust_version("1").is_err());
+        }
+
+        #[test]
+        fn caret_compatibility() {
+            for (a, b, compatible) in [
+                ("1.0.0", "1.9.3", true),
+                ("1.0.0", "2.0.0", false),
+                ("0.1.0", "0.1.5", true),
+                ("0.1.0", "0.2.0", false),
+                ("0.0.1", "0.0.1", true),
+                ("0.0.1", "0.0.2", false),
+            ] {
+                assert_eq!(v(a).is_caret_compatible(&v(b)), compatible, "{} {}", a, b);
+            }
+        }
+
+        #[test]
+        fn requirement_matching() {
+            // (requirement, versions it allows, versions it doesn't)
+            let cases: &[(&str, &[&str], &[&str])] = &[
+                // caret, bare is the same
+                ("^1.2.3", &["1.2.3", "1.2.9", "1.9.0"], &["1.2.2", "2.0.0"]),
+                ("1.2.3", &["1.2.3", "1.9.0"], &["1.2.2", "2.0.0"]),
+                ("^1.2", &["1.2.0", "1.9.9"], &["1.1.9", "2.0.0"]),
+                ("^1", &["1.0.0", "1.9.9"], &["0.9.9", "2.0.0"]),
+                ("^0.2.3", &["0.2.3", "0.2.9"], &["0.2.2", "0.3.0"]),
+                ("^0.2", &["0.2.0", "0.2.9"], &["0.1.9", "0.3.0"]),
+                ("^0.0.3", &["0.0.3"], &["0.0.2", "0.0.4", "0.1.0"]),
+                ("^0.0", &["0.0.0", "0.0.9"], &["0.1.0"]),
+                ("^0", &["0.0.0", "0.9.9"], &["1.0.0"]),
+                // tilde
+                ("~1.2.3", &["1.2.3", "1.2.9"], &["1.2.2", "1.3.0"]),
+                ("~1.2", &["1.2.0", "1.2.9"], &["1.1.9", "1.3.0"]),
+                ("~1", &["1.0.0", "1.9.9"], &["0.9.9", "2.0.0"]),
+                ("~0.2.3", &["0.2.3", "0.2.9"], &["0.2.2", "0.3.0"]),
+                // wildcards
+                ("*", &["0.0.0", "1.2.3", "99.0.0"], &[]),
+                ("1.*", &["1.0.0", "1.9.9"], &["0.9.9", "2.0.0"]),
+                ("1.2.*", &["1.2.0", "1.2.9"], &["1.1.9", "1.3.0"]),
+                ("1.x", &["1.0.0"], &["2.0.0"]),
+                // exact and comparisons
+                ("=1.2.3", &["1.2.3"], &["1.2.2", "1.2.4"]),
+                ("=1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+                (">1.2.3", &["1.2.4", "2.0.0"], &["1.2.3", "1.0.0"]),
+                (">1.2", &["1.3.0"], &["1.2.9"]),
+                (">1", &["2.0.0"], &["1.9.9"]),
+                (">=1.2", &["1.2.0", "3.0.0"], &["1.1.9"]),
+                ("<1.2.3", &["1.2.2", "0.1.0"], &["1.2.3"]),
+                ("<1.2", &["1.1.9"], &["1.2.0"]),
+                ("<=1.2", &["1.2.9", "1.0.0"], &["1.3.0"]),
+                (">=1.2, <1.5", &["1.2.0", "1.4.9"], &["1.1.0", "1.5.0"]),
+                (">= 1.2 , < 2", &["1.9.0"], &["2.0.0"]),
+                // pre-releases only match a comparator naming the same
+                // major.minor.patch
+                (
+                    "^1.2.3-alpha.1",
+                    &[
+                        "1.2.3-alpha.1",
+                        "1.2.3-alpha.2",
+                        "1.2.3-beta",
+                        "1.2.3",
+                        "1.3.0",
+                    ],
+                    &["1.2.3-alpha.0", "1.3.0-alpha", "2.0.0"],
+                ),
+                (
+                    "~1.2.3-beta",
+                    &["1.2.3-beta", "1.2.3-rc.1", "1.2.4"],
+                    &["1.2.3-alpha", "1.3.0"],
+                ),
+                ("=1.2.3-rc.1", &["1.2.3-rc.1"], &["1.2.3", "1.2.3-rc.2"]),
+                (
+                    ">=1.2.3-beta",
+                    &["1.2.3-rc.1", "1.2.3", "2.0.0"],
+                    &["1.2.3-alpha", "1.2.4-beta"],
+                ),
+                ("1.2.3", &["1.2.4"], &["1.2.4-beta", "1.2.3-beta"]),
+                ("*", &[], &["1.0.0-alpha"]),
+                (
+                    "^0.0.3-beta",
+                    &["0.0.3-beta", "0.0.3"],
+                    &["0.0.4", "0.0.3-alpha"],
+                ),
+            ];
+            for (req, yes, no) in cases {
+                let r = VersionReq::parse(req).unwrap();
+                for s in *yes {
+                    assert!(r.matches(&v(s)), "{} should match {}", req, s);
+                }
+                for s in *no {
+                    assert!(!r.matches(&v(s)), "{} should not match {}", req, s);
+                }
+            }
+        }
+
+        #[test]
+        fn requirement_parsing() {
+            for (s, shown) in [
+                ("1.2.3", "1.2.3"),
+                ("^ 1.2", "^1.2"),
+                ("~1", "~1"),
+                ("1.*", "1.*"),
+                ("1.2.x", "1.2.*"),
+                ("*", "*"),
+                (">= 1.2, < 2", ">=1.2, <2"),
+                ("=1.2.3-rc.1+meta", "=1.2.3-rc.1"),
+            ] {
+                assert_eq!(VersionReq::parse(s).unwrap().to_string(), shown, "{}", s);
+            }
+            for s in [
+                "", " ", "^*", "~1.*", ">1.*", "1.*.3", "1.2.3.4", "1.2-beta", "a.b", "1,", "^",
+            ] {
+                assert!(VersionReq::parse(s).is_err(), "{:?}", s);
+            }
+        }
+
+        #[test]
+        fn requirement_lower_bound() {
+            for (req, bound) in [
+                ("1.2", Some("1.2.0")),
+                ("^0.0.3", Some("0.0.3")),
+                ("~1", Some("1.0.0")),
+                ("1.2.*", Some("1.2.0")),
+                (">=1, <2", Some("1.0.0")),
+                (">=1.1, >=1.4.2", Some("1.4.2")),
+                ("~1.2.3-beta", Some("1.2.3-beta")),
+                ("<2", None),
+                ("*", None),
+            ] {
+                let lb = VersionReq::parse(req).unwrap().lower_bound();
+                assert_eq!(lb, bound.map(v), "{}", req);
+            }
+        }
+
+        #[test]
+        fn requirement_bump() {
+            for (req, to, bumped) in [
+                ("1.2", "1.5.0", Some("1.5.0")),
+                ("^0.3", "0.4.1", Some("^0.4.1")),
+                ("~1.2.3", "1.3.0", Some("~1.3.0")),
+                ("=1.0.0", "1.0.5", Some("=1.0.5")),
+                ("1", "2.0.0-rc.1", Some("2.0.0-rc.1")),
+                (">=1, <2", "1.5.0", None),
+                (">=1", "1.5.0", None),
+                ("1.*", "1.5.0", None),
+                ("*", "1.5.0", None),
+            ] {
+                let b = VersionReq::parse(req).unwrap().bump(&v(to));
+                assert_eq!(
+                    b.map(|b| b.to_string()).as_deref(),
+                    bumped,
+                    "{} -> {}",
+                    req,
+                    to
+                );
+            }
         }
     }
 }
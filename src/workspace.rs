@@ -0,0 +1,349 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+
+use crate::manifest::Manifest;
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    /// Its `Cargo.toml`.
+    pub path: PathBuf,
+}
+
+/// Cargo workspace: the root manifest with `[workspace]` and the packages
+/// its `members` globs (minus `exclude`) point at.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    /// Root `Cargo.toml`.
+    pub root: PathBuf,
+    /// The root has no `[package]` of its own.
+    pub is_virtual: bool,
+    pub members: Vec<Member>,
+    /// Manifests of `default-members`, empty if not set.
+    pub default_members: Vec<PathBuf>,
+}
+
+/// Which packages a command works on.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub packages: Vec<String>,
+    /// Every member.
+    pub workspace: bool,
+    /// Every member and the root, even if it is a virtual manifest.
+    pub all: bool,
+}
+
+impl Workspace {
+    /// Workspace the manifest is a member (or the root) of, `None` for a
+    /// package on its own.
+    pub fn find<P: AsRef<Path>>(manifest: P) -> Result<Option<Self>> {
+        let manifest = fs::canonicalize(manifest.as_ref())?;
+        let content = Manifest::load(&manifest)?;
+        let dir = manifest.parent().unwrap_or(Path::new("/"));
+
+        let root = if let Some(rel) = content.package_field("workspace") {
+            dir.join(rel).join("Cargo.toml")
+        } else if content.is_workspace() {
+            manifest.clone()
+        } else {
+            match Self::find_root(dir)? {
+                Some(root) => root,
+                None => return Ok(None),
+            }
+        };
+
+        let ws = Self::load(root)?;
+        if ws.root == manifest || ws.members.iter().any(|m| m.path == manifest) {
+            Ok(Some(ws))
+        } else {
+            info!(
+                "{} is not a member of the workspace at {}",
+                manifest.display(),
+                ws.root.display()
+            );
+            Ok(None)
+        }
+    }
+    /// Closest manifest with `[workspace]` above `dir`.
+    fn find_root(dir: &Path) -> Result<Option<PathBuf>> {
+        let mut dir = dir.parent();
+        while let Some(d) = dir {
+            let path = d.join("Cargo.toml");
+            if path.is_file() && Manifest::load(&path)?.is_workspace() {
+                return Ok(Some(path));
+            }
+            dir = d.parent();
+        }
+        Ok(None)
+    }
+    pub fn load<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = fs::canonicalize(root.as_ref())
+            .map_err(|e| anyhow!("workspace root {}: {}", root.as_ref().display(), e))?;
+        let content = Manifest::load(&root)?;
+        if !content.is_workspace() {
+            return Err(anyhow!("{} has no [workspace]", root.display()));
+        }
+        let dir = root.parent().unwrap_or(Path::new("/"));
+        let is_virtual = content.package_field("name").is_none();
+
+        let mut paths = Vec::new();
+        if !is_virtual {
+            paths.push(root.clone());
+        }
+        for path in Self::expand(dir, &content.workspace_list("members"))? {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        let exclude = content
+            .workspace_list("exclude")
+            .iter()
+            .map(|e| dir.join(e))
+            .collect::<Vec<_>>();
+        paths.retain(|p| !exclude.iter().any(|e| p.starts_with(e)) || *p == root);
+
+        let mut members = Vec::new();
+        for path in paths {
+            let name = Manifest::load(&path)?
+                .package_field("name")
+                .ok_or(anyhow!("no package.name in {}", path.display()))?;
+            members.push(Member { name, path });
+        }
+
+        Ok(Self {
+            default_members: Self::expand(dir, &content.workspace_list("default-members"))?,
+            root,
+            is_virtual,
+            members,
+        })
+    }
+    /// Manifests of the directories the patterns match.
+    fn expand(dir: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+        let mut res = Vec::new();
+        for pattern in patterns {
+            let dirs = glob(dir, pattern)?;
+            if dirs.is_empty() {
+                warn!("workspace member {} matches nothing", pattern);
+            }
+            for d in dirs {
+                let path = d.join("Cargo.toml");
+                if !path.is_file() {
+                    warn!("workspace member {} has no Cargo.toml", d.display());
+                    continue;
+                }
+                let path = fs::canonicalize(path)?;
+                if !res.contains(&path) {
+                    res.push(path);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn member<S: AsRef<str>>(&self, name: S) -> Option<&Member> {
+        self.members.iter().find(|m| m.name == name.as_ref())
+    }
+}
+
+/// Paths matching a `members` pattern relative to `dir`, components may use
+/// `*`, `?` or be `**` for any depth.
+fn glob(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut cur = vec![dir.to_path_buf()];
+    for comp in pattern.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        let mut next = Vec::new();
+        for d in cur {
+            if comp == "**" {
+                subdirs(&d, &mut next)?;
+            } else if comp.contains(['*', '?']) {
+                if !d.is_dir() {
+                    continue;
+                }
+                for entry in fs::read_dir(&d)?.flatten() {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if entry.path().is_dir() && wildcard(comp, &name) {
+                        next.push(entry.path());
+                    }
+                }
+            } else {
+                next.push(d.join(comp));
+            }
+        }
+        cur = next;
+    }
+    cur.sort();
+    Ok(cur)
+}
+
+/// `dir` and every directory below it.
+fn subdirs(dir: &Path, res: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    res.push(dir.to_path_buf());
+    for entry in fs::read_dir(dir)?.flatten() {
+        if entry.path().is_dir() {
+            subdirs(&entry.path(), res)?;
+        }
+    }
+    Ok(())
+}
+
+fn wildcard(pat: &str, s: &str) -> bool {
+    let (pat, s) = (
+        pat.chars().collect::<Vec<_>>(),
+        s.chars().collect::<Vec<_>>(),
+    );
+    // star: position in the pattern after the last `*` and the text
+    // position it was tried at
+    let (mut p, mut i, mut star) = (0, 0, None);
+    while i < s.len() {
+        if p < pat.len() && (pat[p] == '?' || pat[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pat.len() && pat[p] == '*' {
+            star = Some((p + 1, i));
+            p += 1;
+        } else if let Some((sp, si)) = star {
+            p = sp;
+            i = si + 1;
+            star = Some((sp, si + 1));
+        } else {
+            return false;
+        }
+    }
+    pat[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("depi-ws-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for (rel, content) in files {
+            let path = dir.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::canonicalize(dir).unwrap()
+    }
+    fn package(name: &str) -> String {
+        format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name)
+    }
+
+    #[test]
+    fn wildcards() {
+        for (pat, s, m) in [
+            ("*", "anything", true),
+            ("*", "", true),
+            ("a*", "abc", true),
+            ("a*", "bac", false),
+            ("*-cli", "depi-cli", true),
+            ("*-cli", "depi-cli-old", false),
+            ("a?c", "abc", true),
+            ("a?c", "ac", false),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYc-", false),
+            ("exact", "exact", true),
+            ("exact", "exactly", false),
+        ] {
+            assert_eq!(wildcard(pat, s), m, "{} {}", pat, s);
+        }
+    }
+
+    #[test]
+    fn members_and_exclude() {
+        let a = package("a");
+        let (b, ex, tool, deep) = (package("b"), package("ex"), package("gen"), package("x"));
+        let dir = tree(
+            "members",
+            &[
+                (
+                    "Cargo.toml",
+                    "[workspace]\nmembers = [\"crates/*\", \"tools/**\", \"missing\", \"crates/ex\"]\nexclude = [\"crates/ex\"]\ndefault-members = [\"crates/a\"]\n",
+                ),
+                ("crates/a/Cargo.toml", &a),
+                ("crates/b/Cargo.toml", &b),
+                ("crates/ex/Cargo.toml", &ex),
+                ("crates/no-manifest/README.md", ""),
+                ("tools/gen/Cargo.toml", &tool),
+                ("tools/deep/x/Cargo.toml", &deep),
+                ("outside/Cargo.toml", &package("outside")),
+            ],
+        );
+
+        let ws = Workspace::load(dir.join("Cargo.toml")).unwrap();
+        assert!(ws.is_virtual);
+        let names = ws
+            .members
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "x", "gen"]);
+        assert_eq!(ws.default_members, [dir.join("crates/a/Cargo.toml")]);
+        assert_eq!(
+            ws.member("gen").unwrap().path,
+            dir.join("tools/gen/Cargo.toml")
+        );
+        assert!(ws.member("ex").is_none());
+
+        let found = Workspace::find(dir.join("tools/deep/x/Cargo.toml")).unwrap();
+        assert_eq!(found.unwrap().root, dir.join("Cargo.toml"));
+        assert!(Workspace::find(dir.join("Cargo.toml")).unwrap().is_some());
+        // excluded and unlisted packages are on their own
+        assert!(
+            Workspace::find(dir.join("crates/ex/Cargo.toml"))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            Workspace::find(dir.join("outside/Cargo.toml"))
+                .unwrap()
+                .is_none()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn root_package_and_workspace_key() {
+        let dir = tree(
+            "root-package",
+            &[
+                (
+                    "Cargo.toml",
+                    &format!("{}\n[workspace]\nmembers = [\"./m\"]\n", package("root")),
+                ),
+                ("m/Cargo.toml", &package("m")),
+                (
+                    "nested/p/Cargo.toml",
+                    &format!("{}workspace = \"../..\"\n", package("p")),
+                ),
+            ],
+        );
+        let ws = Workspace::load(dir.join("Cargo.toml")).unwrap();
+        assert!(!ws.is_virtual);
+        let names = ws
+            .members
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["root", "m"]);
+
+        // `package.workspace` points at a root that does not list it
+        assert!(
+            Workspace::find(dir.join("nested/p/Cargo.toml"))
+                .unwrap()
+                .is_none()
+        );
+        assert!(Workspace::load(dir.join("m/Cargo.toml")).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+use std::process::{Command, Output};
+use std::{env, fs, process};
+
+const FIXTURE: &str = r#"{
+    "serde": [{"vers": "1.0.100"}, {"vers": "1.0.200"}],
+    "serde_json": [{"vers": "1.0.0"}, {"vers": "1.0.140"}],
+    "json": [{"vers": "0.12.4"}]
+}"#;
+
+/// Runs `depi outdated` in a fresh project with the dependencies and the
+/// crates locked at the given versions, against `FIXTURE` instead of
+/// crates.io.
+fn outdated(name: &str, deps: &str, locked: &[(&str, &str)]) -> Output {
+    let dir = env::temp_dir().join(format!("depi-outdated-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"p\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+            deps
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("Cargo.lock"),
+        format!(
+            "version = 4\n\n[[package]]\nname = \"p\"\nversion = \"0.1.0\"\ndependencies = [{}]\n{}",
+            locked
+                .iter()
+                .map(|(n, _)| format!("\"{}\"", n))
+                .collect::<Vec<_>>()
+                .join(", "),
+            locked
+                .iter()
+                .map(|(n, v)| format!(
+                    "\n[[package]]\nname = \"{}\"\nversion = \"{}\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+                    n, v
+                ))
+                .collect::<String>()
+        ),
+    )
+    .unwrap();
+    let fixture = dir.join("fixture.json");
+    fs::write(&fixture, FIXTURE).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_depi"))
+        .args(["outdated", "-c", "none"])
+        .current_dir(&dir)
+        .env("DEPI_REGISTRY", format!("fixture:{}", fixture.display()))
+        .env("CARGO_HOME", dir.join("cargo-home"))
+        .env("HOME", &dir)
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    out
+}
+
+fn row(out: &Output, name: &str) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    stdout
+        .lines()
+        .map(|l| l.split_whitespace().map(String::from).collect::<Vec<_>>())
+        .find(|cols| cols.first().is_some_and(|c| c == name))
+        .unwrap_or_else(|| panic!("no row for {} in:\n{}", name, stdout))
+}
+
+#[test]
+fn up_to_date_succeeds() {
+    let out = outdated("current", "serde = \"1.0.200\"\n", &[("serde", "1.0.200")]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(
+        row(&out, "serde"),
+        ["serde", "1.0.200", "1.0.200", "1.0.200", "1.0.200"]
+    );
+}
+
+#[test]
+fn outdated_fails() {
+    let out = outdated("behind", "serde = \"1.0.100\"\n", &[("serde", "1.0.100")]);
+    assert!(!out.status.success());
+    assert_eq!(
+        row(&out, "serde"),
+        ["serde", "1.0.100", "1.0.100", "1.0.200", "1.0.200"]
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("outdated dependencies: 1"));
+}
+
+#[test]
+fn renamed_dep_is_checked_against_its_package() {
+    let out = outdated(
+        "renamed",
+        "json = { package = \"serde_json\", version = \"1.0.140\" }\n",
+        &[("serde_json", "1.0.140")],
+    );
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(
+        row(&out, "json"),
+        ["json", "1.0.140", "1.0.140", "1.0.140", "1.0.140"]
+    );
+}